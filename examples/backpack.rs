@@ -0,0 +1,86 @@
+//! The backpack demo: loads `assets/scenes/backpack.json` and lets you fly around it.
+//! Press Tab to grab the cursor and look around with the mouse.
+//!
+//! Run with `cargo run --example backpack`. Pass `--record <path>` to record the session's input,
+//! and `--replay <path>` to re-run a recording headlessly.
+
+use bevy_ecs::{prelude::EventReader, system::ResMut};
+use engine::{
+    DefaultPlugins,
+    common::Time,
+    ecs::{EcsBuilder, Incomplete, PluginGroup, Update},
+    input::{ExitOnEscPlugin, InputEvent},
+    render::{RenderPlugin, camera::{Camera, CameraPlugin}},
+    replay::{InputRecorderPlugin, InputReplay, replay_runner},
+    runner::winit_runner,
+    scene::ScenePlugin,
+    snapshot::SnapshotPlugin,
+    window::{CursorGrab, WindowInfo},
+};
+use winit::event::VirtualKeyCode;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let arg_value = |flag: &str| {
+        args.iter().position(|arg| arg == flag).and_then(|i| args.get(i + 1)).cloned()
+    };
+
+    // `--replay <path>` re-runs a session recorded with `--record <path>` without a window
+    if let Some(path) = arg_value("--replay") {
+        replay(path);
+        return;
+    }
+
+    let ecs_builder = EcsBuilder::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(ScenePlugin::new(SCENE));
+    let mut ecs_builder = add_gameplay(ecs_builder);
+    if let Some(path) = arg_value("--record") {
+        ecs_builder = ecs_builder.add_plugin(InputRecorderPlugin::new(&path));
+    }
+    ecs_builder
+        .set_runner(winit_runner)
+        .build()
+        .run();
+}
+
+const SCENE: &str = "assets/scenes/backpack.json";
+
+/// Everything reacting to input, shared by live and replayed sessions so they end up in the same state
+fn add_gameplay(ecs_builder: EcsBuilder<Incomplete>) -> EcsBuilder<Incomplete> {
+    ecs_builder
+        .add_plugin(ExitOnEscPlugin)
+        .add_plugin(SnapshotPlugin::default())
+        .add_system(toggle_mouse_look, Update)
+}
+
+/// Replay a recorded session headlessly and log where the camera ended up.
+/// There's no GL context, so the scene is spawned without loading its models.
+fn replay(path: String) {
+    let ecs_builder = EcsBuilder::new()
+        .add_plugins(DefaultPlugins.build().disable::<RenderPlugin>())
+        .add_plugin(CameraPlugin)
+        .add_plugin(ScenePlugin::headless(SCENE));
+    let mut world = add_gameplay(ecs_builder)
+        .insert_resource(InputReplay { path })
+        .set_runner(replay_runner)
+        .build()
+        .run();
+
+    let frame_count = world.resource::<Time>().frame_count;
+    for camera in world.query::<&Camera>().iter(&world) {
+        log::info!("after {frame_count} frames the camera is at {} facing {}", camera.position, camera.forward);
+    }
+}
+
+/// Grab and hide the cursor when Tab is pressed, so the camera turns with the mouse, and release it on the next press
+fn toggle_mouse_look(mut input_rdr: EventReader<InputEvent>, mut window_info: ResMut<WindowInfo>) {
+    let tab_pressed = input_rdr.iter().any(|evt| {
+        evt.0.keydowns.as_ref().is_some_and(|keys| keys.contains(&VirtualKeyCode::Tab))
+    });
+    if tab_pressed {
+        let grab = window_info.cursor_grab == CursorGrab::None;
+        window_info.cursor_grab = if grab { CursorGrab::Locked } else { CursorGrab::None };
+        window_info.cursor_visible = !grab;
+    }
+}
@@ -1,17 +1,365 @@
-use std::time::{Duration, Instant};
+use std::{collections::VecDeque, time::{Duration, Instant}};
 
-use bevy_ecs::{system::Resource, world::World};
+use bevy_ecs::{prelude::Component, system::{Resource, Query, Res}, world::World};
 
+use serde::{Deserialize, Serialize};
 
-#[derive(Resource)]
+use crate::ecs::{Plugin, EcsBuilder, Incomplete, First};
+
+
+/// Human-readable name of an entity, e.g. for scene files and debugging
+#[derive(Component, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Name(pub String);
+
+pub struct TimePlugin;
+impl Plugin for TimePlugin {
+    fn build(&self, ecs_builder: EcsBuilder<Incomplete>) -> EcsBuilder<Incomplete> {
+        ecs_builder
+            .insert_resource(Time::default())
+            .add_systems((tick_timers, tick_stopwatches), First)
+    }
+}
+
+#[derive(Resource, Clone)]
 pub struct Time {
+    /// Scaled time elapsed since startup in seconds. Doesn't advance while paused.
     pub current: f32,
+    /// Scaled and clamped time since the last frame in seconds. Zero while paused.
     pub delta: f32,
+    /// Time since the last frame, clamped to `max_delta` but neither scaled nor paused
+    pub unscaled_delta: f32,
+    /// Wall-clock time elapsed since startup
+    pub unscaled_current: f32,
+    /// Number of frames since startup, including paused frames
+    pub frame_count: u64,
+    /// Frames per second, smoothed over the last few frames
+    pub fps: f32,
+    pub paused: bool,
+    /// Multiplier applied to `delta`, e.g. 0.5 for slow motion
+    pub time_scale: f32,
+    /// Upper bound for a single frame's delta, so a hitch (e.g. dragging the window)
+    /// doesn't make everything jump
+    pub max_delta: f32,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self {
+            current: 0.0,
+            delta: 0.0,
+            unscaled_delta: 0.0,
+            unscaled_current: 0.0,
+            frame_count: 0,
+            fps: 0.0,
+            paused: false,
+            time_scale: 1.0,
+            max_delta: 0.25,
+        }
+    }
+}
+
+impl Time {
+    /// Weight of the newest frame in the smoothed FPS value
+    const FPS_SMOOTHING: f32 = 0.1;
+
+    /// Advance by `raw_delta` seconds of real time
+    pub fn update_with_delta(&mut self, raw_delta: f32) {
+        self.frame_count += 1;
+        self.unscaled_current += raw_delta;
+        self.unscaled_delta = raw_delta.min(self.max_delta);
+
+        if raw_delta > 0.0 {
+            let fps = 1.0 / raw_delta;
+            self.fps = if self.fps == 0.0 {
+                fps
+            } else {
+                self.fps + (fps - self.fps) * Self::FPS_SMOOTHING
+            };
+        }
+
+        self.delta = if self.paused { 0.0 } else { self.unscaled_delta * self.time_scale };
+        self.current += self.delta;
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn unpause(&mut self) {
+        self.paused = false;
+    }
 }
 
 pub fn update_time_res(start_time: Instant, world: &mut World) {
     let mut time_res = world.get_resource_mut::<Time>().unwrap();
-    let new_time = Instant::now() - start_time;
-    time_res.delta = new_time.as_secs_f32() - time_res.current;
-    time_res.current = new_time.as_secs_f32();
-}
\ No newline at end of file
+    let new_time = (Instant::now() - start_time).as_secs_f32();
+    let raw_delta = new_time - time_res.unscaled_current;
+    time_res.update_with_delta(raw_delta);
+}
+
+/// Advance the Time resource by a simulated delta instead of the wall clock
+pub fn advance_time_res(delta: f32, world: &mut World) {
+    let mut time_res = world.get_resource_mut::<Time>().unwrap();
+    time_res.update_with_delta(delta);
+}
+
+/// Counts down from a duration, optionally restarting every time it finishes.
+/// Timer components are ticked with the scaled `Time::delta` at the start of every frame.
+#[derive(Component, Clone, Debug)]
+pub struct Timer {
+    duration: f32,
+    elapsed: f32,
+    repeating: bool,
+    paused: bool,
+    finished: bool,
+    times_finished_this_tick: u32,
+}
+
+impl Timer {
+    pub fn new(duration: f32, repeating: bool) -> Self {
+        Self {
+            duration,
+            elapsed: 0.0,
+            repeating,
+            paused: false,
+            finished: false,
+            times_finished_this_tick: 0,
+        }
+    }
+
+    pub fn tick(&mut self, delta: f32) -> &Self {
+        self.times_finished_this_tick = 0;
+        if self.paused || (self.finished && !self.repeating) {
+            return self;
+        }
+
+        self.elapsed += delta;
+        if self.elapsed >= self.duration {
+            self.finished = true;
+            if self.repeating && self.duration > 0.0 {
+                self.times_finished_this_tick = (self.elapsed / self.duration) as u32;
+                self.elapsed %= self.duration;
+            } else {
+                self.times_finished_this_tick = 1;
+                self.elapsed = self.duration;
+            }
+        } else if self.repeating {
+            self.finished = false;
+        }
+        self
+    }
+
+    /// Whether the timer has reached its duration. Repeating timers are only finished on the tick they wrapped.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Whether the timer finished during the last tick
+    pub fn just_finished(&self) -> bool {
+        self.times_finished_this_tick > 0
+    }
+
+    /// How many times a repeating timer wrapped during the last tick
+    pub fn times_finished_this_tick(&self) -> u32 {
+        self.times_finished_this_tick
+    }
+
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+
+    pub fn remaining(&self) -> f32 {
+        self.duration - self.elapsed
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    /// Fraction of the duration that has elapsed, in [0, 1]
+    pub fn percent(&self) -> f32 {
+        if self.duration > 0.0 { self.elapsed / self.duration } else { 1.0 }
+    }
+
+    pub fn set_duration(&mut self, duration: f32) {
+        self.duration = duration;
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn unpause(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn reset(&mut self) {
+        self.elapsed = 0.0;
+        self.finished = false;
+        self.times_finished_this_tick = 0;
+    }
+}
+
+/// Counts up from zero until reset.
+/// Stopwatch components are ticked with the scaled `Time::delta` at the start of every frame.
+#[derive(Component, Clone, Debug, Default)]
+pub struct Stopwatch {
+    elapsed: f32,
+    paused: bool,
+}
+
+impl Stopwatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tick(&mut self, delta: f32) -> &Self {
+        if !self.paused {
+            self.elapsed += delta;
+        }
+        self
+    }
+
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn unpause(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn reset(&mut self) {
+        self.elapsed = 0.0;
+    }
+}
+
+pub fn tick_timers(mut timers: Query<&mut Timer>, time: Res<Time>) {
+    for mut timer in &mut timers {
+        timer.tick(time.delta);
+    }
+}
+
+pub fn tick_stopwatches(mut stopwatches: Query<&mut Stopwatch>, time: Res<Time>) {
+    for mut stopwatch in &mut stopwatches {
+        stopwatch.tick(time.delta);
+    }
+}
+
+/// Drives the FixedUpdate schedule at a constant rate, independent of the frame rate.
+#[derive(Resource, Clone)]
+pub struct FixedTime {
+    /// Length of a single fixed tick in seconds
+    pub step: f32,
+    /// Maximum number of ticks run in one frame before the remaining backlog is dropped
+    pub max_steps: u32,
+    /// How far the accumulator is between the last tick and the next one, in [0, 1).
+    /// Render systems can use this to blend between the previous and the current tick.
+    pub alpha: f32,
+    accumulator: f32,
+}
+
+impl FixedTime {
+    pub fn new(step: f32, max_steps: u32) -> Self {
+        assert!(step > 0.0, "fixed timestep must be positive");
+        Self {
+            step,
+            max_steps,
+            alpha: 0.0,
+            accumulator: 0.0,
+        }
+    }
+
+    pub fn accumulate(&mut self, delta: f32) {
+        self.accumulator += delta;
+    }
+
+    /// Consume one step from the accumulator, returning false if there isn't enough time left
+    pub fn expend(&mut self) -> bool {
+        if self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Drop all whole steps still owed, keeping only the fractional remainder
+    pub fn discard_backlog(&mut self) {
+        self.accumulator %= self.step;
+    }
+
+    pub fn update_alpha(&mut self) {
+        self.alpha = self.accumulator / self.step;
+    }
+}
+
+impl Default for FixedTime {
+    fn default() -> Self {
+        Self::new(1.0 / 60.0, 5)
+    }
+}
+
+/// A history of at most `capacity` items, oldest first, dropping the oldest when full
+#[derive(Clone, Debug)]
+pub struct RingBuffer<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T> RingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self { items: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    /// Add the newest item, dropping the oldest if the buffer is full.
+    /// With a capacity of 0 nothing is kept.
+    pub fn push(&mut self, item: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.items.len() == self.capacity {
+            self.items.pop_front();
+        }
+        self.items.push_back(item);
+    }
+
+    pub fn pop_newest(&mut self) -> Option<T> {
+        self.items.pop_back()
+    }
+
+    pub fn newest(&self) -> Option<&T> {
+        self.items.back()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+        self.items.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+}
@@ -0,0 +1,287 @@
+use std::{collections::BTreeMap, fs, io};
+
+use bevy_ecs::system::{Res, ResMut, Resource};
+use serde::Deserialize;
+use winit::event::VirtualKeyCode;
+
+use crate::{
+    ecs::{Plugin, EcsBuilder, Incomplete, StartupSingleThreaded},
+    error::{EngineError, StartupWarnings},
+    input::KeyBindings,
+    window::{CursorGrab, GraphicsSettings, VsyncMode, WindowInfo, WindowMode},
+};
+
+
+/// Loads window, graphics and key binding settings from a TOML file at startup.
+///
+/// Inserts the `WindowInfo` and `GraphicsSettings` resources, so it must be added before the plugins
+/// that read them. Key bindings are applied at startup, once every plugin has registered its actions.
+/// A missing file means every setting keeps its default.
+/// Unknown keys and invalid settings are added to the startup report as warnings, and invalid
+/// settings are replaced by their defaults.
+pub struct ConfigPlugin {
+    pub path: String,
+}
+
+impl Default for ConfigPlugin {
+    fn default() -> Self {
+        Self { path: "engine.toml".into() }
+    }
+}
+
+impl Plugin for ConfigPlugin {
+    fn build(&self, ecs_builder: EcsBuilder<Incomplete>) -> EcsBuilder<Incomplete> {
+        let (config, warning) = match EngineConfig::load(&self.path) {
+            Ok((config, errors)) if errors.is_empty() => (config, None),
+            Ok((config, errors)) => (config, Some(EngineError::Config { path: self.path.clone(), errors })),
+            Err(err) => (EngineConfig::default(), Some(err)),
+        };
+
+        let ecs_builder = match warning {
+            Some(warning) => ecs_builder.add_startup_warning(warning),
+            None => ecs_builder,
+        };
+        let path = self.path.clone();
+        ecs_builder
+            .insert_resource(config.window_info())
+            .insert_resource(config.graphics_settings())
+            .init_resource::<KeyBindings>()
+            .insert_resource(config)
+            .add_system(
+                move |config: Res<EngineConfig>, bindings: ResMut<KeyBindings>, warnings: ResMut<StartupWarnings>| {
+                    apply_key_bindings(&path, config, bindings, warnings)
+                },
+                StartupSingleThreaded,
+            )
+    }
+}
+
+/// Bind the actions registered by plugins to the keys set in the config
+fn apply_key_bindings(
+    path: &str,
+    config: Res<EngineConfig>,
+    mut bindings: ResMut<KeyBindings>,
+    mut warnings: ResMut<StartupWarnings>,
+) {
+    let mut errors = Vec::new();
+    for (action, key) in &config.bindings {
+        match bindings.0.get_mut(action) {
+            // Keys were checked by `EngineConfig::validate`
+            Some(bound) => *bound = parse_key(key).unwrap(),
+            None => errors.push(format!("unknown action \"{action}\" in bindings")),
+        }
+    }
+    if !errors.is_empty() {
+        warnings.0.push(EngineError::Config { path: path.into(), errors });
+    }
+}
+
+/// Contents of the config file, after validation
+#[derive(Resource, Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct EngineConfig {
+    pub window: WindowConfig,
+    pub graphics: GraphicsConfig,
+    /// Action name to key name, e.g. `move_forward = "W"`.
+    /// Actions left out keep the default key given by the plugin registering them.
+    pub bindings: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    pub width: u32,
+    pub height: u32,
+    pub title: String,
+    pub resizable: bool,
+    pub mode: WindowMode,
+    pub decorations: bool,
+    pub transparent: bool,
+    pub cursor_grab: CursorGrab,
+    pub cursor_visible: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct GraphicsConfig {
+    pub vsync: VsyncMode,
+    pub msaa_samples: u8,
+}
+
+const VALID_MSAA_SAMPLES: [u8; 6] = [0, 1, 2, 4, 8, 16];
+
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        let info = WindowInfo::default();
+        Self {
+            width: info.width,
+            height: info.height,
+            title: info.title,
+            resizable: info.resizable,
+            mode: info.mode,
+            decorations: info.decorations,
+            transparent: info.transparent,
+            cursor_grab: info.cursor_grab,
+            cursor_visible: info.cursor_visible,
+        }
+    }
+}
+
+impl Default for GraphicsConfig {
+    fn default() -> Self {
+        let settings = GraphicsSettings::default();
+        Self {
+            vsync: settings.vsync,
+            msaa_samples: settings.msaa_samples,
+        }
+    }
+}
+
+impl EngineConfig {
+    /// Read and validate a config file, returning it with a description of every unknown key
+    /// and invalid setting.
+    /// A missing file gives the default config, and invalid settings are replaced by their
+    /// defaults. Fails only if the file can't be read or parsed at all.
+    pub fn load(path: &str) -> Result<(Self, Vec<String>), EngineError> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                log::info!("no config found at {path}, using the default settings");
+                return Ok((Self::default(), Vec::new()));
+            },
+            Err(source) => return Err(EngineError::Io { path: path.into(), source }),
+        };
+
+        let (config, errors) = Self::parse(&source).map_err(|err| EngineError::Config {
+            path: path.into(),
+            errors: vec![err.to_string().trim_end().into()],
+        })?;
+        log::info!("loaded config {path}");
+        Ok((config, errors))
+    }
+
+    /// Parse and validate the contents of a config file.
+    /// Unknown keys are ignored rather than rejected, so a typo doesn't lose the other settings.
+    pub fn parse(source: &str) -> Result<(Self, Vec<String>), toml::de::Error> {
+        let mut errors = Vec::new();
+        let mut config: Self = serde_ignored::deserialize(toml::Deserializer::new(source), |key| {
+            errors.push(format!("unknown key \"{key}\""));
+        })?;
+        errors.extend(config.validate());
+        Ok((config, errors))
+    }
+
+    /// Replace every invalid setting by its default, returning a description of each one
+    pub fn validate(&mut self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let default = Self::default();
+
+        if self.window.width == 0 || self.window.height == 0 {
+            errors.push(format!(
+                "window size must be non-zero, got {}x{}", self.window.width, self.window.height
+            ));
+            self.window.width = default.window.width;
+            self.window.height = default.window.height;
+        }
+        if !VALID_MSAA_SAMPLES.contains(&self.graphics.msaa_samples) {
+            errors.push(format!(
+                "graphics.msaa_samples must be one of {VALID_MSAA_SAMPLES:?}, got {}", self.graphics.msaa_samples
+            ));
+            self.graphics.msaa_samples = default.graphics.msaa_samples;
+        }
+
+        // Unknown actions can only be told apart once every plugin has registered its actions,
+        // see `apply_key_bindings`
+        self.bindings.retain(|action, key| {
+            let valid = parse_key(key).is_some();
+            if !valid {
+                errors.push(format!("unknown key \"{key}\" bound to \"{action}\""));
+            }
+            valid
+        });
+
+        errors
+    }
+
+    pub fn window_info(&self) -> WindowInfo {
+        WindowInfo {
+            width: self.window.width,
+            height: self.window.height,
+            title: self.window.title.clone(),
+            resizable: self.window.resizable,
+            mode: self.window.mode,
+            decorations: self.window.decorations,
+            transparent: self.window.transparent,
+            cursor_grab: self.window.cursor_grab,
+            cursor_visible: self.window.cursor_visible,
+        }
+    }
+
+    pub fn graphics_settings(&self) -> GraphicsSettings {
+        GraphicsSettings {
+            vsync: self.graphics.vsync,
+            msaa_samples: self.graphics.msaa_samples,
+        }
+    }
+}
+
+/// Parse a key from its `VirtualKeyCode` variant name, e.g. "W", "Space" or "LShift"
+fn parse_key(name: &str) -> Option<VirtualKeyCode> {
+    toml::Value::String(name.into()).try_into().ok()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{headless::{HeadlessRunner, headless_runner}, render::camera::CameraPlugin};
+
+    #[test]
+    fn unknown_keys_keep_the_other_settings() {
+        let source = "
+            colour = 1
+
+            [window]
+            widht = 800
+            height = 600
+            title = \"test\"
+
+            [graphics]
+            msaa_samples = 3
+        ";
+        let (config, errors) = EngineConfig::parse(source).unwrap();
+
+        assert_eq!(config.window.height, 600);
+        assert_eq!(config.window.title, "test");
+        assert_eq!(config.window.width, WindowConfig::default().width);
+        assert_eq!(config.graphics.msaa_samples, GraphicsConfig::default().msaa_samples);
+        assert_eq!(errors.len(), 3, "{errors:?}");
+        assert!(errors[0].contains("colour"));
+        assert!(errors[1].contains("window.widht"));
+        assert!(errors[2].contains("msaa_samples"));
+    }
+
+    #[test]
+    fn bindings_override_the_registered_actions() {
+        let path = std::env::temp_dir().join(format!("engine_bindings_test_{}.toml", std::process::id())).to_string_lossy().into_owned();
+        fs::write(&path, "[bindings]\nmove_forward = \"Up\"\njump = \"Space\"\n").unwrap();
+        let world = EcsBuilder::new()
+            .add_plugin(ConfigPlugin { path: path.clone() })
+            .add_plugin(CameraPlugin)
+            // Only the startup schedules
+            .insert_resource(HeadlessRunner { frames: 0, delta: 1.0 / 60.0 })
+            .set_runner(headless_runner)
+            .build()
+            .run();
+        let _ = fs::remove_file(&path);
+
+        let bindings = world.resource::<KeyBindings>();
+        assert_eq!(bindings.get("move_forward"), Some(VirtualKeyCode::Up));
+        assert_eq!(bindings.get("move_backward"), Some(VirtualKeyCode::S));
+        assert_eq!(bindings.get("jump"), None);
+        let warnings = &world.resource::<StartupWarnings>().0;
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].to_string().contains("jump"), "{}", warnings[0]);
+    }
+}
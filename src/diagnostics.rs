@@ -0,0 +1,445 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    fs,
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bevy_ecs::{system::Resource, world::World};
+use serde::Serialize;
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id},
+    subscriber::Interest,
+    Metadata, Subscriber,
+};
+use tracing_subscriber::{layer::{Context, SubscriberExt}, registry::LookupSpan, Layer, Registry};
+
+use crate::{
+    common::RingBuffer,
+    ecs::{Plugin, EcsBuilder, Incomplete, Last},
+    error::EngineError,
+};
+
+
+/// Records frame time, schedule and system durations, and entity and resource counts every frame
+/// into the `Diagnostics` resource.
+///
+/// Requires the `diagnostics` feature, which enables the "trace" feature of bevy_ecs.
+/// Durations come from the spans bevy_ecs emits around every schedule and system run,
+/// collected by a `tracing` subscriber this plugin installs.
+/// Only the schedules run by `run_update_schedules` and the Render schedule are measured, each World
+/// getting its own (see `collect_timings`). They are gathered at the end of the Last schedule,
+/// so the Last and Render schedules show up in the next frame's measurements.
+pub struct DiagnosticsPlugin {
+    /// Number of frames kept in the history
+    pub history_len: usize,
+}
+
+impl Default for DiagnosticsPlugin {
+    fn default() -> Self {
+        Self { history_len: 300 }
+    }
+}
+
+impl Plugin for DiagnosticsPlugin {
+    fn build(&self, ecs_builder: EcsBuilder<Incomplete>) -> EcsBuilder<Incomplete> {
+        // Process-wide, so only the first Ecs installs it (see `Ecs`)
+        let subscriber = Registry::default().with(TimingLayer);
+        if tracing::subscriber::set_global_default(subscriber).is_ok() {
+            TIMING_LAYER_INSTALLED.store(true, Ordering::Relaxed);
+        } else if !TIMING_LAYER_INSTALLED.load(Ordering::Relaxed) {
+            log::warn!("another tracing subscriber is already installed, schedule and system timings are unavailable");
+        }
+
+        ecs_builder
+            .insert_resource(Diagnostics::new(self.history_len))
+            .add_system(record_diagnostics, Last)
+    }
+}
+
+/// Measurements of a single frame. Durations are in milliseconds.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FrameDiagnostics {
+    pub frame: u64,
+    /// Wall-clock time since the previous frame
+    pub frame_time: f64,
+    pub fps: f64,
+    /// Total time spent in each schedule, keyed by label (e.g. "Update").
+    /// Schedules run several times per frame, like FixedUpdate, are summed.
+    pub schedules: BTreeMap<String, f64>,
+    /// Total time spent in each system, keyed by its full path
+    pub systems: BTreeMap<String, f64>,
+    pub entity_count: u32,
+    pub resource_count: usize,
+}
+
+/// Rolling history of the measurements of the last `capacity` frames, oldest first.
+///
+/// Schedule and system timings are only recorded if `DiagnosticsPlugin` could install its
+/// subscriber as the global default. If the program installed another tracing subscriber first,
+/// `schedules` and `systems` stay empty and only frame times and counts are recorded.
+#[derive(Resource)]
+pub struct Diagnostics {
+    history: RingBuffer<FrameDiagnostics>,
+    frame_count: u64,
+    last_frame: Option<Instant>,
+    /// Spans of this World closed since the last `record_diagnostics`
+    span_timings: SpanTimings,
+}
+
+impl Diagnostics {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            history: RingBuffer::new(capacity),
+            frame_count: 0,
+            last_frame: None,
+            span_timings: SpanTimings::default(),
+        }
+    }
+
+    pub fn history(&self) -> impl Iterator<Item = &FrameDiagnostics> {
+        self.history.iter()
+    }
+
+    pub fn latest(&self) -> Option<&FrameDiagnostics> {
+        self.history.newest()
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    /// Average frame time over the history in milliseconds
+    pub fn average_frame_time(&self) -> Option<f64> {
+        average(self.history.iter().map(|frame| frame.frame_time))
+    }
+
+    pub fn average_fps(&self) -> Option<f64> {
+        self.average_frame_time().filter(|ms| *ms > 0.0).map(|ms| 1000.0 / ms)
+    }
+
+    /// Average time spent in a schedule per frame, counting frames where it didn't run as 0
+    pub fn average_schedule_time(&self, label: &str) -> Option<f64> {
+        average(self.history.iter().map(|frame| frame.schedules.get(label).copied().unwrap_or(0.0)))
+    }
+
+    /// Average time spent in a system per frame, counting frames where it didn't run as 0
+    pub fn average_system_time(&self, name: &str) -> Option<f64> {
+        average(self.history.iter().map(|frame| frame.systems.get(name).copied().unwrap_or(0.0)))
+    }
+
+    /// The `count` systems with the highest average time, slowest first
+    pub fn slowest_systems(&self, count: usize) -> Vec<(String, f64)> {
+        let names: BTreeSet<&String> = self.history.iter().flat_map(|frame| frame.systems.keys()).collect();
+        let mut systems: Vec<(String, f64)> = names.into_iter()
+            .map(|name| (name.clone(), self.average_system_time(name).unwrap_or(0.0)))
+            .collect();
+        systems.sort_by(|a, b| b.1.total_cmp(&a.1));
+        systems.truncate(count);
+        systems
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&self.history.iter().collect::<Vec<_>>())
+    }
+
+    /// One row per frame. Every schedule and system seen in the history gets a column,
+    /// prefixed with "schedule:" or "system:", left empty for frames where it didn't run.
+    pub fn to_csv(&self) -> String {
+        let schedules: BTreeSet<&String> = self.history.iter().flat_map(|frame| frame.schedules.keys()).collect();
+        let systems: BTreeSet<&String> = self.history.iter().flat_map(|frame| frame.systems.keys()).collect();
+
+        let mut csv = String::from("frame,frame_time,fps,entity_count,resource_count");
+        for name in &schedules {
+            csv += &format!(",{}", csv_field(&format!("schedule:{name}")));
+        }
+        for name in &systems {
+            csv += &format!(",{}", csv_field(&format!("system:{name}")));
+        }
+        csv.push('\n');
+
+        for frame in self.history.iter() {
+            csv += &format!("{},{},{},{},{}",
+                frame.frame, frame.frame_time, frame.fps, frame.entity_count, frame.resource_count);
+            for name in &schedules {
+                csv += &format!(",{}", frame.schedules.get(*name).map_or(String::new(), f64::to_string));
+            }
+            for name in &systems {
+                csv += &format!(",{}", frame.systems.get(*name).map_or(String::new(), f64::to_string));
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    pub fn export_json(&self, path: &str) -> Result<(), EngineError> {
+        fs::write(path, self.to_json()?)
+            .map_err(|source| EngineError::Io { path: path.into(), source })
+    }
+
+    pub fn export_csv(&self, path: &str) -> Result<(), EngineError> {
+        fs::write(path, self.to_csv())
+            .map_err(|source| EngineError::Io { path: path.into(), source })
+    }
+}
+
+fn average(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+/// Quote a CSV field if it contains a separator or quote, e.g. system names with generics
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.into()
+    }
+}
+
+pub fn record_diagnostics(world: &mut World) {
+    let now = Instant::now();
+    let entity_count = world.entities().len();
+    let resource_count = world.storages().resources.iter().filter(|(_, data)| data.is_present()).count()
+        + world.storages().non_send_resources.iter().filter(|(_, data)| data.is_present()).count();
+
+    let mut diagnostics = world.resource_mut::<Diagnostics>();
+    let timings = std::mem::take(&mut *diagnostics.span_timings.lock().unwrap());
+    let mut schedules = BTreeMap::new();
+    let mut systems = BTreeMap::new();
+    for timing in timings {
+        let durations = match timing.kind {
+            SpanKind::Schedule => &mut schedules,
+            SpanKind::System => &mut systems,
+        };
+        *durations.entry(timing.name).or_insert(0.0) += timing.busy.as_secs_f64() * 1000.0;
+    }
+
+    let frame_time = diagnostics.last_frame
+        .map_or(0.0, |last_frame| (now - last_frame).as_secs_f64() * 1000.0);
+    diagnostics.last_frame = Some(now);
+    diagnostics.frame_count += 1;
+
+    let frame = FrameDiagnostics {
+        frame: diagnostics.frame_count,
+        frame_time,
+        fps: if frame_time > 0.0 { 1000.0 / frame_time } else { 0.0 },
+        schedules,
+        systems,
+        entity_count,
+        resource_count,
+    };
+    diagnostics.history.push(frame);
+}
+
+
+/// Whether the subscriber collecting span timings is the global default
+static TIMING_LAYER_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+type SpanTimings = Arc<Mutex<Vec<SpanTiming>>>;
+
+thread_local! {
+    /// Where the timings of the spans opened on this thread without a parent go, see `collect_timings`
+    static CURRENT_TIMINGS: RefCell<Option<SpanTimings>> = const { RefCell::new(None) };
+}
+
+/// Send the timings of the schedules run on this thread to `world`'s Diagnostics until the scope is dropped.
+/// Spans opened inside those schedules, even on other threads, go to the same World,
+/// so several Worlds in one process each get their own timings.
+/// Does nothing if `world` has no Diagnostics.
+pub fn collect_timings(world: &World) -> TimingScope {
+    let timings = world.get_resource::<Diagnostics>().map(|diagnostics| diagnostics.span_timings.clone());
+    TimingScope { previous: CURRENT_TIMINGS.with(|current| current.replace(timings)) }
+}
+
+/// Restores the previous destination of span timings when dropped, see `collect_timings`
+pub struct TimingScope {
+    previous: Option<SpanTimings>,
+}
+
+impl Drop for TimingScope {
+    fn drop(&mut self) {
+        CURRENT_TIMINGS.with(|current| *current.borrow_mut() = self.previous.take());
+    }
+}
+
+/// Timings of the World a span was opened for, inherited from its parent span
+struct TimingSink(SpanTimings);
+
+/// Spans of bevy_ecs, including those that aren't timed but link systems run on other threads
+/// to their schedule (e.g. "multithreaded executor")
+fn is_ecs_span(metadata: &Metadata) -> bool {
+    metadata.target().starts_with("bevy_ecs") && metadata.is_span()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SpanKind {
+    Schedule,
+    System,
+}
+
+impl SpanKind {
+    fn of(metadata: &Metadata) -> Option<Self> {
+        if !is_ecs_span(metadata) {
+            return None;
+        }
+        match metadata.name() {
+            "schedule" => Some(SpanKind::Schedule),
+            "system" => Some(SpanKind::System),
+            _ => None,
+        }
+    }
+}
+
+struct SpanTiming {
+    kind: SpanKind,
+    name: String,
+    /// Time spent inside the span, which a system task may enter several times
+    busy: Duration,
+    entered: Option<Instant>,
+}
+
+/// Measures how long the schedule and system spans of bevy_ecs are entered
+struct TimingLayer;
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for TimingLayer {
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        if is_ecs_span(metadata) { Interest::always() } else { Interest::never() }
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: Context<'_, S>) -> bool {
+        is_ecs_span(metadata)
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let inherited = span.parent()
+            .and_then(|parent| parent.extensions().get::<TimingSink>().map(|sink| sink.0.clone()));
+        let Some(timings) = inherited.or_else(|| CURRENT_TIMINGS.with(|current| current.borrow().clone())) else {
+            return;
+        };
+        span.extensions_mut().insert(TimingSink(timings));
+
+        let Some(kind) = SpanKind::of(attrs.metadata()) else { return };
+        let mut visitor = NameVisitor(String::new());
+        attrs.record(&mut visitor);
+        span.extensions_mut().insert(SpanTiming {
+            kind,
+            name: visitor.0,
+            busy: Duration::ZERO,
+            entered: None,
+        });
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<SpanTiming>() {
+                timing.entered = Some(Instant::now());
+            }
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<SpanTiming>() {
+                if let Some(entered) = timing.entered.take() {
+                    timing.busy += entered.elapsed();
+                }
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else { return };
+        let mut extensions = span.extensions_mut();
+        let Some(timing) = extensions.remove::<SpanTiming>() else { return };
+        if let Some(TimingSink(timings)) = extensions.get_mut::<TimingSink>() {
+            timings.lock().unwrap().push(timing);
+        }
+    }
+}
+
+/// Reads the `name` field of a span, recorded as a string for systems and with Debug for schedules
+struct NameVisitor(String);
+
+impl Visit for NameVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "name" {
+            self.0 = value.into();
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "name" {
+            self.0 = format!("{value:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::schedule::IntoSystemConfig;
+
+    use super::*;
+    use crate::{ecs::Update, headless::{HeadlessRunner, headless_runner}};
+
+    fn system_a() {}
+    fn system_b() {}
+
+    fn run<M>(system: impl IntoSystemConfig<M>, history_len: usize, frames: u32) -> World {
+        EcsBuilder::new()
+            .add_plugin(DiagnosticsPlugin { history_len })
+            .add_system(system, Update)
+            .insert_resource(HeadlessRunner { frames, delta: 0.1 })
+            .set_runner(headless_runner)
+            .build()
+            .run()
+    }
+
+    fn ran_system(diagnostics: &Diagnostics, name: &str) -> bool {
+        diagnostics.history().any(|frame| frame.systems.keys().any(|system| system.ends_with(name)))
+    }
+
+    #[test]
+    fn each_world_records_its_own_timings() {
+        let (world_a, world_b) = std::thread::scope(|scope| {
+            let a = scope.spawn(|| run(system_a, 10, 5));
+            let b = scope.spawn(|| run(system_b, 10, 5));
+            (a.join().unwrap(), b.join().unwrap())
+        });
+
+        let (diagnostics_a, diagnostics_b) = (world_a.resource::<Diagnostics>(), world_b.resource::<Diagnostics>());
+        assert!(ran_system(diagnostics_a, "system_a") && !ran_system(diagnostics_a, "system_b"));
+        assert!(ran_system(diagnostics_b, "system_b") && !ran_system(diagnostics_b, "system_a"));
+        assert!(diagnostics_a.average_schedule_time("Update").is_some());
+    }
+
+    #[test]
+    fn history_keeps_the_last_frames_and_exports_them() {
+        let world = run(system_a, 2, 3);
+        let diagnostics = world.resource::<Diagnostics>();
+
+        let frames: Vec<u64> = diagnostics.history().map(|frame| frame.frame).collect();
+        assert_eq!(frames, [2, 3]);
+        assert_eq!(diagnostics.latest().unwrap().frame, 3);
+
+        let csv = diagnostics.to_csv();
+        let mut lines = csv.lines();
+        let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+        assert_eq!(header[..5], ["frame", "frame_time", "fps", "entity_count", "resource_count"]);
+        assert!(header.contains(&"schedule:Update"), "{header:?}");
+        assert!(header.iter().any(|column| column.starts_with("system:") && column.ends_with("system_a")), "{header:?}");
+        let rows: Vec<Vec<&str>> = lines.map(|line| line.split(',').collect()).collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|row| row.len() == header.len()));
+        assert_eq!((rows[0][0], rows[1][0]), ("2", "3"));
+
+        let json: serde_json::Value = serde_json::from_str(&diagnostics.to_json().unwrap()).unwrap();
+        let json_frames: Vec<u64> = json.as_array().unwrap().iter().map(|frame| frame["frame"].as_u64().unwrap()).collect();
+        assert_eq!(json_frames, [2, 3]);
+        assert!(json[1]["schedules"]["Update"].as_f64().is_some());
+    }
+}
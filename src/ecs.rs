@@ -1,5 +1,10 @@
-use std::{marker::PhantomData};
-use bevy_ecs::{prelude::*, schedule::{ScheduleLabel, ExecutorKind}};
+use std::{marker::PhantomData, collections::HashMap, any::{Any, TypeId}, time::Duration};
+use bevy_ecs::event::ManualEventReader;
+use bevy_ecs::{prelude::*, schedule::{ScheduleLabel, ExecutorKind, run_enter_schedule}};
+
+use crate::{common::{Time, FixedTime}, error::{AssetErrors, EngineError, StartupErrors, StartupWarnings}};
+
+pub use bevy_ecs::schedule::{States, State, NextState, OnEnter, OnExit, OnUpdate, common_conditions::in_state};
 
 
 /* Schedule Labels */
@@ -9,24 +14,119 @@ pub struct StartupSingleThreaded;
 #[derive(ScheduleLabel, Hash, Debug, Eq, PartialEq, Clone)]
 pub struct Startup;
 
+/// Runs at the very start of every frame, before any app logic (e.g. event buffer updates)
+#[derive(ScheduleLabel, Hash, Debug, Eq, PartialEq, Clone)]
+pub struct First;
+
 #[derive(ScheduleLabel, Hash, Debug, Eq, PartialEq, Clone)]
 pub struct PreUpdate;
 
+/// Applies queued `NextState<S>` transitions, running the `OnExit`/`OnEnter` schedules
+#[derive(ScheduleLabel, Hash, Debug, Eq, PartialEq, Clone)]
+pub struct StateTransition;
+
+#[derive(ScheduleLabel, Hash, Debug, Eq, PartialEq, Clone)]
+pub struct FixedUpdate;
+
 #[derive(ScheduleLabel, Hash, Debug, Eq, PartialEq, Clone)]
 pub struct Update;
 
+/// Runs after Update, for work that reacts to the frame's app logic (e.g. propagation)
+#[derive(ScheduleLabel, Hash, Debug, Eq, PartialEq, Clone)]
+pub struct PostUpdate;
+
+/// Runs at the end of every frame's update schedules, right before Render
+#[derive(ScheduleLabel, Hash, Debug, Eq, PartialEq, Clone)]
+pub struct Last;
+
 #[derive(ScheduleLabel, Hash, Debug, Eq, PartialEq, Clone)]
 pub struct Render;
 /* -------------- */
 
+/// Send this event to shut the app down; the runner exits once the current frame has finished
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AppExit;
+
+/// When the runner runs frames. Insert it as a resource to pick the mode of an app;
+/// systems can also switch modes at runtime, e.g. to Continuous while an animation plays.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum UpdateMode {
+    /// Run frames back to back (limited by vsync)
+    #[default]
+    Continuous,
+    /// Only run a frame when input arrives or a RequestRedraw event has been sent,
+    /// or once `wait` has elapsed since the last frame. Suited to tools and editors.
+    Reactive { wait: Option<Duration> },
+}
+
+/// Send this event to run another frame in `UpdateMode::Reactive`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestRedraw;
+
+/// Decides when a runner should run the next frame, according to the UpdateMode resource.
+/// Times are measured from the runner's start, so headless runners can use simulated time.
+#[derive(Default)]
+pub struct RedrawScheduler {
+    redraw_reader: ManualEventReader<RequestRedraw>,
+    redraw_pending: bool,
+    last_frame: Option<Duration>,
+}
+
+impl RedrawScheduler {
+    /// Input or a window event arrived, so the next frame should run
+    pub fn wake(&mut self) {
+        self.redraw_pending = true;
+    }
+
+    /// Call after every frame, to pick up the RequestRedraw events sent during it
+    pub fn frame_finished(&mut self, world: &World, now: Duration) {
+        let redraw_events = world.resource::<Events<RequestRedraw>>();
+        self.redraw_pending = self.redraw_reader.iter(redraw_events).last().is_some();
+        self.last_frame = Some(now);
+    }
+
+    /// Whether the next frame should run at `now`
+    pub fn should_run(&self, world: &World, now: Duration) -> bool {
+        match self.next_frame(world) {
+            Some(next_frame) => now >= next_frame,
+            None => false,
+        }
+    }
+
+    /// The earliest time the next frame should run, or None to wait for input
+    pub fn next_frame(&self, world: &World) -> Option<Duration> {
+        let Some(last_frame) = self.last_frame else { return Some(Duration::ZERO) };
+        match world.get_resource::<UpdateMode>().copied().unwrap_or_default() {
+            UpdateMode::Continuous => Some(last_frame),
+            UpdateMode::Reactive { .. } if self.redraw_pending => Some(last_frame),
+            UpdateMode::Reactive { wait } => wait.map(|wait| last_frame + wait),
+        }
+    }
+}
+
+/// Request another frame whenever the resource R has changed
+pub fn request_redraw_on_change<R: Resource>(res: Res<R>, mut redraw_wtr: EventWriter<RequestRedraw>) {
+    if res.is_changed() {
+        redraw_wtr.send(RequestRedraw);
+    }
+}
+
+/// A built World and the runner that drives it.
+///
+/// Some plugins install process-wide state that can only be set once: the logger (`LogPlugin`),
+/// the tracing subscriber (`DiagnosticsPlugin`) and the task pools (`TaskPoolPlugin`).
+/// When several Ecs are built in the same process, e.g. by tests, the first one to install
+/// each of them keeps it and the later ones share it.
 pub struct Ecs {
     world: World,
-    runner: fn(World),
+    runner: fn(World) -> World,
 }
 
 impl Ecs {
-    pub fn run(self) {
-        (self.runner)(self.world);
+    /// Hand the World over to the runner.
+    /// Runners that return (e.g. the headless runner) give the World back for inspection.
+    pub fn run(self) -> World {
+        (self.runner)(self.world)
     }
 }
 
@@ -41,10 +141,19 @@ impl EcsBuilderState for Complete {}
 pub struct EcsBuilder<E: EcsBuilderState> {
     world: World,
     schedules: Schedules,
-    runner: Option<fn(World)>,
+    runner: Option<fn(World) -> World>,
+    // Types of all added plugins, mapped to their name and the name of the plugin
+    // that pulled them in as a dependency (if any)
+    plugins: HashMap<TypeId, (String, Option<String>)>,
     state: PhantomData<E>
 }
 
+impl Default for EcsBuilder<Incomplete> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // EcsBuilder can only transition to the Complete state once set_runner is called.
 impl EcsBuilder<Incomplete> {
     pub fn new() -> Self {
@@ -52,6 +161,7 @@ impl EcsBuilder<Incomplete> {
             world: World::new(),
             schedules: Schedules::new(),
             runner: None,
+            plugins: HashMap::new(),
             state: PhantomData,
         }
             .add_schedule({
@@ -60,22 +170,34 @@ impl EcsBuilder<Incomplete> {
                 startup_st
             }, StartupSingleThreaded)
             .add_schedule(Schedule::new(), Startup)
+            .add_schedule(Schedule::new(), First)
             .add_schedule(Schedule::new(), PreUpdate)
+            .add_schedule(Schedule::new(), StateTransition)
+            .add_schedule(Schedule::new(), FixedUpdate)
             .add_schedule(Schedule::new(), Update)
+            .add_schedule(Schedule::new(), PostUpdate)
+            .add_schedule(Schedule::new(), Last)
             .add_schedule({
                 let mut render = Schedule::new();
                 render.set_executor_kind(ExecutorKind::SingleThreaded);
                 render
             }, Render)
+            .insert_resource(FixedTime::default())
+            .insert_resource(StartupErrors::default())
+            .insert_resource(StartupWarnings::default())
+            .insert_resource(AssetErrors::default())
+            .add_event::<AppExit>()
+            .add_event::<RequestRedraw>()
     }
     
 
     // Transition to the Complete state once runner is set
-    pub fn set_runner(self, runner: fn(World)) -> EcsBuilder<Complete> {
+    pub fn set_runner(self, runner: fn(World) -> World) -> EcsBuilder<Complete> {
         EcsBuilder {
             world: self.world,
             schedules: self.schedules,
             runner: Some(runner),
+            plugins: self.plugins,
             state: PhantomData,
         }
     }
@@ -85,15 +207,109 @@ impl EcsBuilder<Incomplete> {
         self
     }
     
+    /// In `UpdateMode::Reactive`, run another frame whenever the resource R changes
+    pub fn redraw_on_change<R: Resource>(self) -> Self {
+        self.add_system(request_redraw_on_change::<R>.run_if(resource_exists::<R>()), Last)
+    }
+    
+    /// Insert the resource's default value unless it has already been inserted (e.g. by a config)
+    pub fn init_resource<R: Resource + Default>(mut self) -> Self {
+        self.world.init_resource::<R>();
+        self
+    }
+    
+    /// Modify a resource, inserting its default value first if needed, so several plugins can add to it
+    pub fn edit_resource<R: Resource + Default>(mut self, edit: impl FnOnce(&mut R)) -> Self {
+        edit(&mut self.world.get_resource_or_insert_with(R::default));
+        self
+    }
+    
+    /// Add a non-fatal problem found while building, e.g. by a plugin, to the startup report
+    pub fn add_startup_warning(mut self, warning: EngineError) -> Self {
+        self.world.resource_mut::<StartupWarnings>().0.push(warning);
+        self
+    }
+    
     pub fn insert_non_send_resource<R: Resource>(mut self, resource: R) -> Self {
         self.world.insert_non_send_resource(resource);
         self
     }
     
+    /// Register an event type and schedule the double-buffer swap of its queue once per frame.
+    /// Events sent during a frame can be read until the end of the following frame.
+    pub fn add_event<T: Event>(self) -> Self {
+        if self.world.contains_resource::<Events<T>>() {
+            return self;
+        }
+        self.insert_resource(Events::<T>::default())
+            .add_system(Events::<T>::update_system, First)
+    }
+    
+    /// Register a state machine for `S`, starting in `S::default()`.
+    /// Queue transitions through the `NextState<S>` resource; they are applied between PreUpdate and Update.
+    /// Systems in Update can be gated with `.in_set(OnUpdate(variant))` or `.run_if(in_state(variant))`.
+    pub fn add_state<S: States>(mut self) -> Self {
+        if self.world.contains_resource::<State<S>>() {
+            panic!("state {} already exists", std::any::type_name::<S>());
+        }
+        self.world.init_resource::<State<S>>();
+        self.world.init_resource::<NextState<S>>();
+
+        self = self.add_systems((
+            // The initial state is entered on the first frame
+            run_enter_schedule::<S>.run_if(run_once()),
+            apply_state_transition::<S>,
+        ).chain(), StateTransition);
+
+        for variant in S::variants() {
+            self = self
+                .configure_set(OnUpdate(variant.clone()).run_if(in_state(variant.clone())), Update)
+                .add_schedule(Schedule::new(), OnEnter(variant.clone()))
+                .add_schedule(Schedule::new(), OnExit(variant));
+        }
+        self
+    }
+    
+    /// Add a plugin, along with any of its dependencies that haven't been added yet.
+    /// Panics if a plugin of the same type has already been added.
     pub fn add_plugin<P: Plugin>(self, plugin: P) -> Self {
+        self.add_dyn_plugin(&plugin, None)
+    }
+    
+    /// Add every enabled plugin of a group, in the group's order
+    pub fn add_plugins<G: PluginGroup>(mut self, group: G) -> Self {
+        for plugin in group.build().into_enabled() {
+            self = self.add_dyn_plugin(plugin.as_ref(), None);
+        }
+        self
+    }
+    
+    fn add_dyn_plugin(mut self, plugin: &dyn Plugin, required_by: Option<&str>) -> Self {
+        let name = plugin.name().to_string();
+        if let Some((_, added_for)) = self.plugins.get(&plugin.type_id()) {
+            match (required_by, added_for) {
+                // The dependency is already satisfied
+                (Some(_), _) => return self,
+                (None, Some(dependant)) => panic!(
+                    "plugin {name} was already added as a dependency of {dependant}; \
+                    add it before {dependant} to configure it yourself"
+                ),
+                (None, None) => panic!("plugin {name} has already been added"),
+            }
+        }
+        self.plugins.insert(plugin.type_id(), (name.clone(), required_by.map(str::to_string)));
+
+        for dependency in plugin.dependencies() {
+            self = self.add_dyn_plugin(dependency.as_ref(), Some(&name));
+        }
         plugin.build(self)
     }
     
+    /// Whether a plugin of type P has been added, directly or as a dependency
+    pub fn is_plugin_added<P: Plugin>(&self) -> bool {
+        self.plugins.contains_key(&TypeId::of::<P>())
+    }
+    
     pub fn add_schedule(mut self, schedule: Schedule, label: impl ScheduleLabel) -> Self {
         let label_clone = label.dyn_clone();
         if self.schedules.insert(label, schedule).is_some() {
@@ -111,6 +327,39 @@ impl EcsBuilder<Incomplete> {
         schedule.add_system(system);
         self
     }
+    
+    /// Add several systems at once, e.g. `(a, b.after(a), c).chain()`
+    pub fn add_systems<M>(mut self,
+        systems: impl IntoSystemConfigs<M>,
+        label: impl ScheduleLabel
+    ) -> Self {
+        let schedule = self.schedules.get_mut(&label)
+            .unwrap_or_else(|| panic!("schedule with label {label:?} does not exist"));
+        schedule.add_systems(systems);
+        self
+    }
+    
+    /// Configure a system set (ordering, run conditions) within a schedule
+    pub fn configure_set(mut self,
+        set: impl IntoSystemSetConfig,
+        label: impl ScheduleLabel
+    ) -> Self {
+        let schedule = self.schedules.get_mut(&label)
+            .unwrap_or_else(|| panic!("schedule with label {label:?} does not exist"));
+        schedule.configure_set(set);
+        self
+    }
+    
+    /// Configure several system sets at once, e.g. `(A, B).chain()`
+    pub fn configure_sets(mut self,
+        sets: impl IntoSystemSetConfigs,
+        label: impl ScheduleLabel
+    ) -> Self {
+        let schedule = self.schedules.get_mut(&label)
+            .unwrap_or_else(|| panic!("schedule with label {label:?} does not exist"));
+        schedule.configure_sets(sets);
+        self
+    }
 }
 
 // Methods for EcsBuilder in the Complete state
@@ -124,8 +373,138 @@ impl EcsBuilder<Complete> {
     }
 }
 
-pub trait Plugin {
+pub trait Plugin: Any {
     /// Configure the Ecs to which this plugin is added.
     /// The plugin will not be able to call `ecs_builder.run()`.
     fn build(&self, ecs_builder: EcsBuilder<Incomplete>) -> EcsBuilder<Incomplete>;
+
+    /// Name of the plugin in messages. Defaults to the type name.
+    /// Plugins are told apart by type, so two plugins of the same type can't be added.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Plugins that must be present for this one to work.
+    /// Any that haven't been added yet are added, with the returned configuration, before this one is built.
+    fn dependencies(&self) -> Vec<Box<dyn Plugin>> {
+        Vec::new()
+    }
+}
+
+/// A set of plugins that are commonly added together
+pub trait PluginGroup {
+    fn build(self) -> PluginGroupBuilder;
+}
+
+/// Ordered list of plugins in which individual plugins can be replaced or disabled
+#[derive(Default)]
+pub struct PluginGroupBuilder {
+    plugins: Vec<(TypeId, Box<dyn Plugin>, bool)>,
+}
+
+impl PluginGroupBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a plugin to the group, or replace the existing plugin of the same type in place
+    pub fn add_plugin<P: Plugin + 'static>(mut self, plugin: P) -> Self {
+        let entry = (TypeId::of::<P>(), Box::new(plugin) as Box<dyn Plugin>, true);
+        match self.plugins.iter_mut().find(|(id, ..)| *id == entry.0) {
+            Some(existing) => *existing = entry,
+            None => self.plugins.push(entry),
+        }
+        self
+    }
+
+    /// Skip a plugin of the group. It can still be added as a dependency of another plugin.
+    pub fn disable<P: Plugin + 'static>(mut self) -> Self {
+        self.set_enabled::<P>(false);
+        self
+    }
+
+    pub fn enable<P: Plugin + 'static>(mut self) -> Self {
+        self.set_enabled::<P>(true);
+        self
+    }
+
+    fn set_enabled<P: Plugin + 'static>(&mut self, enabled: bool) {
+        let (.., plugin_enabled) = self.plugins.iter_mut()
+            .find(|(id, ..)| *id == TypeId::of::<P>())
+            .unwrap_or_else(|| panic!("plugin {} is not part of the group", std::any::type_name::<P>()));
+        *plugin_enabled = enabled;
+    }
+
+    fn into_enabled(self) -> impl Iterator<Item = Box<dyn Plugin>> {
+        self.plugins.into_iter()
+            .filter_map(|(_, plugin, enabled)| enabled.then_some(plugin))
+    }
+}
+
+impl PluginGroup for PluginGroupBuilder {
+    fn build(self) -> PluginGroupBuilder {
+        self
+    }
+}
+
+/// Run every per-frame schedule that doesn't require a GL context, in order
+pub fn run_update_schedules(world: &mut World) {
+    #[cfg(feature = "diagnostics")]
+    let _timings = crate::diagnostics::collect_timings(world);
+    world.run_schedule(First);
+    world.run_schedule(PreUpdate);
+    world.run_schedule(StateTransition);
+    run_fixed_update(world);
+    world.run_schedule(Update);
+    world.run_schedule(PostUpdate);
+    world.run_schedule(Last);
+}
+
+/// Run the FixedUpdate schedule as many times as the time accumulated since the last frame allows
+pub fn run_fixed_update(world: &mut World) {
+    let delta = world.resource::<Time>().delta;
+    world.resource_mut::<FixedTime>().accumulate(delta);
+
+    let mut steps = 0;
+    while world.resource_mut::<FixedTime>().expend() {
+        world.run_schedule(FixedUpdate);
+
+        steps += 1;
+        if steps >= world.resource::<FixedTime>().max_steps {
+            // Too far behind to ever catch up (e.g. after a hitch), so drop the backlog
+            // instead of spiralling into more and more ticks per frame
+            world.resource_mut::<FixedTime>().discard_backlog();
+            break;
+        }
+    }
+
+    world.resource_mut::<FixedTime>().update_alpha();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct RenamedPlugin;
+    impl Plugin for RenamedPlugin {
+        fn build(&self, ecs_builder: EcsBuilder<Incomplete>) -> EcsBuilder<Incomplete> {
+            ecs_builder
+        }
+
+        fn name(&self) -> &str {
+            "renamed"
+        }
+    }
+
+    #[test]
+    fn plugins_are_found_by_type_whatever_their_name() {
+        let ecs_builder = EcsBuilder::new().add_plugin(RenamedPlugin);
+        assert!(ecs_builder.is_plugin_added::<RenamedPlugin>());
+    }
+
+    #[test]
+    #[should_panic(expected = "plugin renamed has already been added")]
+    fn adding_a_plugin_twice_panics() {
+        let _ = EcsBuilder::new().add_plugin(RenamedPlugin).add_plugin(RenamedPlugin);
+    }
 }
@@ -0,0 +1,146 @@
+use std::{collections::HashSet, fmt, io};
+
+use bevy_ecs::{prelude::Entity, system::Resource, world::World};
+
+
+#[derive(Debug)]
+pub enum EngineError {
+    /// A file couldn't be read or written
+    Io { path: String, source: io::Error },
+    ShaderCompile { path: String, log: String },
+    ShaderLink { vert_path: String, frag_path: String, log: String },
+    Texture { path: String, source: image::ImageError },
+    Model { path: String, source: tobj::LoadError },
+    /// The MTL file referenced by an OBJ file couldn't be loaded
+    Material { path: String, source: tobj::LoadError },
+    /// Several files an asset is made of couldn't be loaded
+    Asset { name: String, errors: Vec<EngineError> },
+    /// A whole file couldn't be parsed
+    Parse { path: String, source: serde_json::Error },
+    /// A value couldn't be (de)serialized
+    Json(serde_json::Error),
+    UnknownComponent { name: String },
+    InvalidComponent { name: String, source: Box<EngineError> },
+    /// Some of a scene's entities couldn't be spawned completely
+    Scene { path: String, errors: Vec<EngineError> },
+    /// Some settings of a config file are invalid and were replaced by their defaults
+    Config { path: String, errors: Vec<String> },
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::Io { path, source } => write!(f, "failed to access {path}: {source}"),
+            EngineError::ShaderCompile { path, log } => write!(f, "shader {path} failed to compile:\n{log}"),
+            EngineError::ShaderLink { vert_path, frag_path, log } => {
+                write!(f, "shader program ({vert_path}, {frag_path}) failed to link:\n{log}")
+            },
+            EngineError::Texture { path, source } => write!(f, "failed to load texture {path}: {source}"),
+            EngineError::Model { path, source } => write!(f, "failed to load model {path}: {source}"),
+            EngineError::Material { path, source } => write!(f, "failed to load materials of model {path}: {source}"),
+            EngineError::Asset { name, errors } => {
+                write!(f, "{name} failed to load:")?;
+                for err in errors {
+                    write!(f, "\n  - {err}")?;
+                }
+                Ok(())
+            },
+            EngineError::Parse { path, source } => write!(f, "failed to parse {path}: {source}"),
+            EngineError::Json(source) => write!(f, "{source}"),
+            EngineError::UnknownComponent { name } => write!(f, "unknown component \"{name}\""),
+            EngineError::InvalidComponent { name, source } => write!(f, "invalid component \"{name}\": {source}"),
+            EngineError::Scene { path, errors } => {
+                write!(f, "scene {path} was only partially loaded:")?;
+                for err in errors {
+                    write!(f, "\n  - {err}")?;
+                }
+                Ok(())
+            },
+            EngineError::Config { path, errors } => {
+                write!(f, "config {path} has invalid settings, using their defaults:")?;
+                for err in errors {
+                    write!(f, "\n  - {err}")?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+impl EngineError {
+    /// Report every file of the asset `name` that failed to load as one error.
+    /// `errors` must not be empty.
+    pub fn aggregate(name: String, mut errors: Vec<EngineError>) -> Self {
+        if errors.len() == 1 {
+            errors.pop().unwrap()
+        } else {
+            EngineError::Asset { name, errors }
+        }
+    }
+}
+
+impl From<serde_json::Error> for EngineError {
+    fn from(err: serde_json::Error) -> Self {
+        EngineError::Json(err)
+    }
+}
+
+/// Errors collected by startup systems, so that every failing asset is reported at once
+/// instead of the app stopping at the first one
+#[derive(Resource, Default)]
+pub struct StartupErrors(pub Vec<EngineError>);
+
+/// Problems found during startup that were worked around, e.g. invalid settings replaced by
+/// their defaults. They are part of the startup report but don't make startup fail.
+#[derive(Resource, Default)]
+pub struct StartupWarnings(pub Vec<EngineError>);
+
+/// Errors of assets loaded in the background, e.g. the models of a scene, kept for the app to inspect.
+/// Each error is logged as it happens, except for loads started during startup (see `StartupAssetLoads`).
+#[derive(Resource, Default)]
+pub struct AssetErrors(pub Vec<EngineError>);
+
+/// Background loads started during startup, which usually finish after `report_startup_errors` has run.
+/// Once the last one has finished, their errors are logged as one report and moved to AssetErrors.
+#[derive(Resource, Default)]
+pub struct StartupAssetLoads {
+    /// Entities whose asset is still loading
+    pub pending: HashSet<Entity>,
+    pub errors: Vec<EngineError>,
+}
+
+/// Log every error and warning collected during startup as a single report.
+/// Returns false if startup failed.
+pub fn report_startup_errors(world: &World) -> bool {
+    let warnings = &world.resource::<StartupWarnings>().0;
+    if !warnings.is_empty() {
+        log::warn!("{}", format_report(&format!("startup has {} warning(s):", warnings.len()), warnings));
+    }
+
+    let errors = &world.resource::<StartupErrors>().0;
+    if errors.is_empty() {
+        return true;
+    }
+
+    log::error!("{}", format_report(&format!("startup failed with {} error(s):", errors.len()), errors));
+    false
+}
+
+/// Log the errors of the assets loaded in the background during startup as a single report
+pub fn report_startup_asset_errors(errors: &[EngineError]) {
+    if errors.is_empty() {
+        log::info!("every startup asset has loaded");
+    } else {
+        log::error!("{}", format_report(&format!("{} startup asset(s) failed to load:", errors.len()), errors));
+    }
+}
+
+fn format_report(header: &str, errors: &[EngineError]) -> String {
+    let mut report = header.to_string();
+    for (i, err) in errors.iter().enumerate() {
+        report += &format!("\n{}. {}", i + 1, err.to_string().replace('\n', "\n   "));
+    }
+    report
+}
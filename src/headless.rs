@@ -0,0 +1,274 @@
+use std::time::Duration;
+
+use bevy_ecs::{system::Resource, world::World, event::{Events, ManualEventReader}};
+
+use crate::{common::{Time, advance_time_res}, error::report_startup_errors, ecs::{StartupSingleThreaded, Startup, AppExit, RedrawScheduler, run_update_schedules}};
+
+
+/// Configures `headless_runner`. Insert it as a resource before building the Ecs,
+/// otherwise the default (a single 60 Hz frame) is used.
+#[derive(Resource, Clone)]
+pub struct HeadlessRunner {
+    /// Number of frames to run after the startup schedules.
+    /// In `UpdateMode::Reactive` this is the number of `delta` ticks instead, of which only those
+    /// where a frame is due actually run, with Time advancing by the ticks skipped since the last frame.
+    pub frames: u32,
+    /// Simulated time between two frames in seconds
+    pub delta: f32,
+}
+
+impl Default for HeadlessRunner {
+    fn default() -> Self {
+        Self {
+            frames: 1,
+            delta: 1.0 / 60.0,
+        }
+    }
+}
+
+/// Runner that doesn't open a window or create a GL context, so it can be used in tests and CI.
+/// The Render schedule is never run, and the World is returned once all frames have run,
+/// an AppExit event has been sent, or startup failed (see the StartupErrors resource).
+pub fn headless_runner(mut world: World) -> World {
+    let config = world.get_resource::<HeadlessRunner>().cloned().unwrap_or_default();
+    let mut app_exit_reader = ManualEventReader::<AppExit>::default();
+
+    if !run_startup(&mut world) {
+        return world;
+    }
+
+    let mut redraw_scheduler = RedrawScheduler::default();
+    let mut elapsed = Duration::ZERO;
+    let mut pending_delta = 0.0;
+    for _ in 0..config.frames {
+        elapsed += Duration::from_secs_f32(config.delta);
+        pending_delta += config.delta;
+        if !redraw_scheduler.should_run(&world, elapsed) {
+            continue;
+        }
+
+        advance_time_res(pending_delta, &mut world);
+        pending_delta = 0.0;
+        run_update_schedules(&mut world);
+        redraw_scheduler.frame_finished(&world, elapsed);
+
+        let app_exit_events = world.resource::<Events<AppExit>>();
+        if app_exit_reader.iter(app_exit_events).last().is_some() {
+            break;
+        }
+    }
+
+    world
+}
+
+/// Run the startup schedules without a window. Returns false if startup failed.
+pub fn run_startup(world: &mut World) -> bool {
+    world.init_resource::<Time>();
+    world.run_schedule(StartupSingleThreaded);
+    world.run_schedule(Startup);
+    report_startup_errors(world)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::{
+        prelude::{Entity, EventWriter, With},
+        system::{Commands, Local, Query, Res, ResMut, Resource},
+    };
+    use winit::event::VirtualKeyCode;
+
+    use super::*;
+    use crate::{
+        common::{FixedTime, TimePlugin},
+        ecs::{EcsBuilder, Incomplete, Startup, First, PreUpdate, FixedUpdate, Update, UpdateMode, RequestRedraw},
+        input::{Input, InputEvent, InputStates},
+        render::camera::{Camera, CameraPlugin},
+        window::{SecondaryWindow, WindowInfo, WindowPlugin, WindowRef, WindowResized},
+    };
+
+    fn run(ecs_builder: EcsBuilder<Incomplete>, frames: u32, delta: f32) -> World {
+        ecs_builder
+            .insert_resource(HeadlessRunner { frames, delta })
+            .set_runner(headless_runner)
+            .build()
+            .run()
+    }
+
+    fn camera(world: &mut World) -> &Camera {
+        world.query::<&Camera>().single(world)
+    }
+
+    #[test]
+    fn camera_moves_while_key_held() {
+        let ecs_builder = EcsBuilder::new()
+            .add_plugin(CameraPlugin)
+            .add_system(
+                |mut states: ResMut<InputStates>| { states.keyholds.insert(VirtualKeyCode::W); },
+                PreUpdate,
+            );
+        let mut world = run(ecs_builder, 10, 0.1);
+
+        let camera = camera(&mut world);
+        // Starts at z = 3 facing -z, moving at 10 units per second for 1 second
+        assert!((camera.position.z - (3.0 - 10.0)).abs() < 1e-3, "camera at {}", camera.position);
+        assert!(camera.position.x.abs() < 1e-3);
+    }
+
+    #[test]
+    fn camera_turns_while_look_key_held() {
+        let ecs_builder = EcsBuilder::new()
+            .add_plugin(CameraPlugin)
+            .add_system(
+                |mut states: ResMut<InputStates>| { states.keyholds.insert(VirtualKeyCode::Right); },
+                PreUpdate,
+            );
+        let mut world = run(ecs_builder, 10, 0.1);
+
+        let camera = camera(&mut world);
+        // Default yaw of -90 degrees, turning at 50 degrees per second for 1 second
+        assert!((camera.yaw - (-90.0 + 50.0)).abs() < 1e-3, "yaw {}", camera.yaw);
+        assert!((camera.position.z - 3.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn camera_zooms_on_scroll_and_turns_with_mouse_motion() {
+        let ecs_builder = EcsBuilder::new()
+            .add_plugin(CameraPlugin)
+            .add_system(|mut input_wtr: EventWriter<InputEvent>| {
+                input_wtr.send(InputEvent(Input {
+                    mouse_scroll_delta: 1.0,
+                    mouse_motion: Some(glam::Vec2::new(10.0, 0.0)),
+                    ..Default::default()
+                }));
+            }, PreUpdate);
+        let mut world = run(ecs_builder, 5, 0.1);
+
+        let camera = camera(&mut world);
+        assert_eq!(camera.zoom, 45.0 - 5.0);
+        // 10 units of motion per frame at 0.1 degrees per unit
+        assert!((camera.yaw - (-90.0 + 5.0)).abs() < 1e-3, "yaw {}", camera.yaw);
+    }
+
+    #[derive(Resource, Default)]
+    struct FixedSteps(Vec<u32>);
+
+    fn count_fixed_step(mut steps: ResMut<FixedSteps>) {
+        *steps.0.last_mut().unwrap() += 1;
+    }
+
+    fn start_frame(mut steps: ResMut<FixedSteps>) {
+        steps.0.push(0);
+    }
+
+    fn fixed_steps(step: f32, max_steps: u32, frames: u32, delta: f32) -> Vec<u32> {
+        let ecs_builder = EcsBuilder::new()
+            .insert_resource(FixedTime::new(step, max_steps))
+            .insert_resource(FixedSteps::default())
+            .add_system(start_frame, PreUpdate)
+            .add_system(count_fixed_step, FixedUpdate);
+        run(ecs_builder, frames, delta).remove_resource::<FixedSteps>().unwrap().0
+    }
+
+    #[test]
+    fn fixed_update_runs_once_per_elapsed_step() {
+        assert_eq!(fixed_steps(0.0625, 5, 4, 0.125), vec![2, 2, 2, 2]);
+        // Half a step per frame: every other frame ticks
+        assert_eq!(fixed_steps(0.25, 5, 4, 0.125), vec![0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn fixed_update_drops_backlog_after_max_steps() {
+        // 4 steps owed per frame, but only 2 run and the rest is dropped
+        assert_eq!(fixed_steps(0.0625, 2, 3, 0.25), vec![2, 2, 2]);
+    }
+
+    #[test]
+    fn app_exit_ends_the_run_early() {
+        let ecs_builder = EcsBuilder::new()
+            .add_system(|mut frames: Local<u32>, mut exit_wtr: EventWriter<AppExit>| {
+                *frames += 1;
+                if *frames == 3 {
+                    exit_wtr.send(AppExit);
+                }
+            }, Update);
+        let world = run(ecs_builder, 100, 0.125);
+
+        assert_eq!(world.resource::<Time>().current, 3.0 * 0.125);
+    }
+
+    fn frames_run(update_mode: UpdateMode, ticks: u32, delta: f32, redraw_frames: &'static [u64]) -> u64 {
+        let ecs_builder = EcsBuilder::new()
+            .add_plugin(TimePlugin)
+            .insert_resource(update_mode)
+            .add_system(move |time: Res<Time>, mut redraw_wtr: EventWriter<RequestRedraw>| {
+                if redraw_frames.contains(&time.frame_count) {
+                    redraw_wtr.send(RequestRedraw);
+                }
+            }, Update);
+        run(ecs_builder, ticks, delta).resource::<Time>().frame_count
+    }
+
+    #[test]
+    fn continuous_mode_runs_every_tick() {
+        assert_eq!(frames_run(UpdateMode::Continuous, 5, 0.125, &[]), 5);
+    }
+
+    #[test]
+    fn reactive_mode_only_runs_the_first_frame_without_redraw() {
+        assert_eq!(frames_run(UpdateMode::Reactive { wait: None }, 10, 0.125, &[]), 1);
+    }
+
+    #[test]
+    fn reactive_mode_runs_a_frame_after_request_redraw() {
+        // Frames 1 and 2 each request the next one, then nothing does
+        assert_eq!(frames_run(UpdateMode::Reactive { wait: None }, 10, 0.125, &[1, 2]), 3);
+    }
+
+    #[test]
+    fn reactive_mode_runs_a_frame_once_wait_has_elapsed() {
+        // Ticks at 0.125 s intervals, frames at 0.125, 0.5 and 0.875 s
+        let wait = Some(Duration::from_secs_f32(0.375));
+        assert_eq!(frames_run(UpdateMode::Reactive { wait }, 8, 0.125, &[]), 3);
+    }
+
+    #[test]
+    fn reactive_mode_runs_a_frame_after_input() {
+        let world = EcsBuilder::new()
+            .insert_resource(UpdateMode::Reactive { wait: None })
+            .set_runner(|world| world)
+            .build()
+            .run();
+        let mut scheduler = RedrawScheduler::default();
+        let now = Duration::from_secs(1);
+        scheduler.frame_finished(&world, now);
+        assert!(!scheduler.should_run(&world, now + Duration::from_secs(60)));
+
+        // What winit_runner does for every window or device event
+        scheduler.wake();
+        assert!(scheduler.should_run(&world, now));
+    }
+
+    #[test]
+    fn resize_events_update_window_info() {
+        let ecs_builder = EcsBuilder::new()
+            .add_plugin(WindowPlugin::default())
+            .add_system(
+                |mut commands: Commands| { commands.spawn(SecondaryWindow::default()); },
+                Startup,
+            )
+            .add_system(
+                |mut resized_wtr: EventWriter<WindowResized>, secondary_qry: Query<Entity, With<SecondaryWindow>>| {
+                    resized_wtr.send(WindowResized { window: WindowRef::Primary, width: 1024, height: 768 });
+                    let window = WindowRef::Entity(secondary_qry.single());
+                    resized_wtr.send(WindowResized { window, width: 320, height: 240 });
+                },
+                First,
+            );
+        let mut world = run(ecs_builder, 1, 0.1);
+
+        let window_info = world.resource::<WindowInfo>();
+        assert_eq!((window_info.width, window_info.height), (1024, 768));
+        let secondary = world.query::<&SecondaryWindow>().single(&world);
+        assert_eq!((secondary.info.width, secondary.info.height), (320, 240));
+    }
+}
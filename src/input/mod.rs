@@ -1,37 +1,112 @@
-use std::collections::HashSet;
+use std::collections::{HashSet, HashMap};
 
-use bevy_ecs::{system::Resource, world::World, prelude::Events};
+use bevy_ecs::{system::Resource, world::World, prelude::{EventReader, EventWriter}};
 use glam::Vec2;
-use winit::event::{WindowEvent, VirtualKeyCode, ElementState, MouseScrollDelta};
+use serde::{Deserialize, Serialize};
+use winit::event::{DeviceEvent, WindowEvent, VirtualKeyCode, ElementState, MouseScrollDelta};
 
-use crate::ecs::{Plugin, EcsBuilder, Incomplete};
+use crate::ecs::{Plugin, EcsBuilder, Incomplete, AppExit, Update};
 
 pub struct InputPlugin;
 impl Plugin for InputPlugin {
     fn build(&self, ecs_builder: EcsBuilder<Incomplete>) -> EcsBuilder<Incomplete> {
         ecs_builder
-            .insert_resource(Events::<InputEvent>::default())
+            .add_event::<InputEvent>()
             .insert_resource(InputStates {
-                first_mouse: true,
                 curr_mouse_pos: Vec2::ZERO,
                 keyholds: HashSet::new(),
             })
+            .init_resource::<KeyBindings>()
+    }
+}
+
+/// Exits the app when Escape is pressed
+pub struct ExitOnEscPlugin;
+impl Plugin for ExitOnEscPlugin {
+    fn build(&self, ecs_builder: EcsBuilder<Incomplete>) -> EcsBuilder<Incomplete> {
+        ecs_builder.add_system(exit_on_esc, Update)
+    }
+
+    fn dependencies(&self) -> Vec<Box<dyn Plugin>> {
+        vec![Box::new(InputPlugin)]
+    }
+}
+
+pub fn exit_on_esc(
+    mut input_rdr: EventReader<InputEvent>,
+    mut exit_wtr: EventWriter<AppExit>,
+) {
+    let esc_pressed = input_rdr.iter().any(|evt| {
+        evt.0.keydowns.as_ref().is_some_and(|keys| keys.contains(&VirtualKeyCode::Escape))
+    });
+    if esc_pressed {
+        exit_wtr.send(AppExit);
     }
 }
 
 #[derive(Resource)]
 pub struct InputStates {
-    pub first_mouse: bool,
     pub curr_mouse_pos: Vec2,
     pub keyholds: HashSet<VirtualKeyCode>,
 }
 
+impl InputStates {
+    /// Update the states with an input about to be sent, whether it comes from the window or a replay
+    pub fn apply(&mut self, input: &Input) {
+        if let Some(pos) = input.mouse_pos {
+            self.curr_mouse_pos = pos;
+        }
+        if let Some(keydowns) = &input.keydowns {
+            self.keyholds.extend(keydowns);
+        }
+        if let Some(keyups) = &input.keyups {
+            for key in keyups {
+                self.keyholds.remove(key);
+            }
+        }
+    }
+}
+
+/// Maps named actions (e.g. "move_forward") to the keys that trigger them.
+/// Actions are registered with their default key by the plugins using them, see `EcsBuilder::add_action`,
+/// and the config can bind them to other keys.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct KeyBindings(pub HashMap<String, VirtualKeyCode>);
+
+impl KeyBindings {
+    pub fn get(&self, action: &str) -> Option<VirtualKeyCode> {
+        self.0.get(action).copied()
+    }
+
+    /// Whether the key bound to `action` was pressed in this input
+    pub fn is_pressed(&self, action: &str, input: &Input) -> bool {
+        let Some(key) = self.get(action) else { return false };
+        input.keydowns.as_ref().is_some_and(|keys| keys.contains(&key))
+    }
+
+    /// Whether the key bound to `action` is currently held down
+    pub fn is_held(&self, action: &str, states: &InputStates) -> bool {
+        self.get(action).is_some_and(|key| states.keyholds.contains(&key))
+    }
+}
+
+impl EcsBuilder<Incomplete> {
+    /// Register an action in KeyBindings, bound to `default_key` until the config binds it to another key
+    pub fn add_action(self, action: &str, default_key: VirtualKeyCode) -> Self {
+        self.edit_resource(|bindings: &mut KeyBindings| {
+            bindings.0.entry(action.into()).or_insert(default_key);
+        })
+    }
+}
+
 pub struct InputEvent(pub Input);
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Input {
     pub mouse_pos: Option<Vec2>,
-    pub prev_mouse_pos: Option<Vec2>,
+    /// Raw relative mouse movement, not limited by the window edge nor affected by pointer acceleration.
+    /// Only reported while the cursor is grabbed, see `WindowInfo::cursor_grab`.
+    pub mouse_motion: Option<Vec2>,
     pub mouse_scroll_delta: f32,
     pub keydowns: Option<HashSet<VirtualKeyCode>>,
     pub keyups: Option<HashSet<VirtualKeyCode>>,
@@ -41,7 +116,7 @@ impl Default for Input {
     fn default() -> Self {
         Self {
             mouse_pos: None,
-            prev_mouse_pos: None,
+            mouse_motion: None,
             mouse_scroll_delta: 0.0,
             keydowns: None,
             keyups: None,
@@ -49,65 +124,86 @@ impl Default for Input {
     }
 }
 
-/// Redirect the window's input events into the Input resource
+/// Redirect the window's input events into the Input resource.
+/// The runner only passes the primary window's events, so typing in a secondary window doesn't move the camera.
 pub fn process_input_event(
     event: &WindowEvent,
     world: &mut World,
 ) {
-    let mut input_res = Input::default();
-    let mut input_changed = false;
-
-    match event {
-        WindowEvent::CursorMoved { position, .. } => {
-            input_changed = true;
-            let mut states = world.get_resource_mut::<InputStates>().unwrap();
-            let pos = Vec2::new(position.x as f32, position.y as f32);
-            
-            if states.first_mouse {
-                states.first_mouse = false;
-                states.curr_mouse_pos = pos;
-            }
-            
-            input_res.mouse_pos = Some(pos);
-            input_res.prev_mouse_pos = Some(states.curr_mouse_pos);
-            states.curr_mouse_pos = pos;
-        },
-        WindowEvent::MouseWheel { delta, .. } => {
-            input_changed = true;
-            match delta {
-                MouseScrollDelta::LineDelta(_, y) => input_res.mouse_scroll_delta = *y,
-                MouseScrollDelta::PixelDelta(pos) => input_res.mouse_scroll_delta = pos.y as f32,
-            }
+    let input_res = match event {
+        WindowEvent::CursorMoved { position, .. } => Input {
+            mouse_pos: Some(Vec2::new(position.x as f32, position.y as f32)),
+            ..Default::default()
         },
-        WindowEvent::CursorEntered { .. } => {
-            input_changed = true;
-            let mut states = world.get_resource_mut::<InputStates>().unwrap();
-            states.first_mouse = true;
+        WindowEvent::MouseWheel { delta, .. } => Input {
+            mouse_scroll_delta: match delta {
+                MouseScrollDelta::LineDelta(_, y) => *y,
+                MouseScrollDelta::PixelDelta(pos) => pos.y as f32,
+            },
+            ..Default::default()
         },
         WindowEvent::KeyboardInput { input, .. } => {
-            if let Some(key) = input.virtual_keycode {
-                input_changed = true;
-                let mut states = world.get_resource_mut::<InputStates>().unwrap();
-                match input.state {
-                    ElementState::Pressed => {
-                        input_res.keydowns.get_or_insert(HashSet::new());
-                        input_res.keydowns.as_mut().unwrap().insert(key);
-                        states.keyholds.insert(key);
-                    },
-                    ElementState::Released => {
-                        input_res.keyups.get_or_insert(HashSet::new());
-                        input_res.keyups.as_mut().unwrap().insert(key);
-                        // WARNING: key may not be removed if window loses focus before
-                        // user releases key
-                        states.keyholds.remove(&key);
-                    },
-                }
+            let Some(key) = input.virtual_keycode else { return };
+            match input.state {
+                ElementState::Pressed => Input { keydowns: Some(HashSet::from([key])), ..Default::default() },
+                ElementState::Released => Input { keyups: Some(HashSet::from([key])), ..Default::default() },
             }
-        }
-        _ => ()
+        },
+        // Keys released while another window has focus are never reported, so release them now
+        WindowEvent::Focused(false) => {
+            let keyholds = &world.resource::<InputStates>().keyholds;
+            if keyholds.is_empty() {
+                return;
+            }
+            Input { keyups: Some(keyholds.clone()), ..Default::default() }
+        },
+        _ => return,
+    };
+
+    world.resource_mut::<InputStates>().apply(&input_res);
+    world.send_event(InputEvent(input_res));
+}
+
+/// Redirect raw mouse motion into InputEvents
+pub fn process_device_event(
+    event: &DeviceEvent,
+    world: &mut World,
+) {
+    if let DeviceEvent::MouseMotion { delta: (x, y) } = event {
+        world.send_event(InputEvent(Input {
+            mouse_motion: Some(Vec2::new(*x as f32, *y as f32)),
+            ..Default::default()
+        }));
     }
-    
-    if input_changed {
-        world.send_event(InputEvent(input_res));
+}
+
+/// Send a previously recorded input, updating InputStates the way `process_input_event` did
+/// when the input was first produced
+pub fn replay_input(input: Input, world: &mut World) {
+    world.resource_mut::<InputStates>().apply(&input);
+    world.send_event(InputEvent(input));
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::event::Events;
+
+    use super::*;
+
+    #[test]
+    fn losing_focus_releases_held_keys() {
+        let mut world = EcsBuilder::new()
+            .add_plugin(InputPlugin)
+            .set_runner(|world| world)
+            .build()
+            .run();
+        world.resource_mut::<InputStates>().keyholds.insert(VirtualKeyCode::W);
+
+        process_input_event(&WindowEvent::Focused(false), &mut world);
+
+        assert!(world.resource::<InputStates>().keyholds.is_empty());
+        let events = world.resource::<Events<InputEvent>>();
+        let keyups: Vec<_> = events.iter_current_update_events().flat_map(|evt| evt.0.keyups.clone()).collect();
+        assert_eq!(keyups, vec![HashSet::from([VirtualKeyCode::W])]);
     }
-}
\ No newline at end of file
+}
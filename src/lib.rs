@@ -0,0 +1,43 @@
+pub mod common;
+pub mod config;
+#[cfg(feature = "diagnostics")]
+pub mod diagnostics;
+pub mod ecs;
+pub mod error;
+pub mod headless;
+pub mod input;
+pub mod logging;
+pub mod render;
+pub mod replay;
+pub mod runner;
+pub mod scene;
+pub mod snapshot;
+pub mod tasks;
+pub mod transform;
+pub mod window;
+
+use common::TimePlugin;
+use config::ConfigPlugin;
+use ecs::{PluginGroup, PluginGroupBuilder};
+use input::InputPlugin;
+use logging::LogPlugin;
+use render::RenderPlugin;
+use tasks::TaskPoolPlugin;
+use transform::TransformPlugin;
+use window::WindowPlugin;
+
+/// The engine's built-in plugins
+pub struct DefaultPlugins;
+impl PluginGroup for DefaultPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::new()
+            .add_plugin(LogPlugin::default())
+            .add_plugin(ConfigPlugin::default())
+            .add_plugin(TaskPoolPlugin::default())
+            .add_plugin(TimePlugin)
+            .add_plugin(WindowPlugin::default())
+            .add_plugin(InputPlugin)
+            .add_plugin(TransformPlugin)
+            .add_plugin(RenderPlugin)
+    }
+}
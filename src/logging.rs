@@ -0,0 +1,172 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    sync::Mutex,
+    time::Instant,
+};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::ecs::{Plugin, EcsBuilder, Incomplete};
+
+
+/// Name of the environment variable that overrides `LogPlugin::filter`
+pub const LOG_ENV_VAR: &str = "ENGINE_LOG";
+
+/// Routes messages from the `log` macros (`info!`, `warn!`, ...) to stderr and optionally a file.
+///
+/// Filters are comma-separated directives, each either a level applying to every module
+/// or `module::path=level` applying to that module and its children, e.g. `warn,engine::render=debug`.
+/// The most specific directive matching a message's module wins.
+pub struct LogPlugin {
+    /// Used unless the ENGINE_LOG environment variable is set
+    pub filter: String,
+    /// Also append every message to this file
+    pub file: Option<String>,
+}
+
+impl Default for LogPlugin {
+    fn default() -> Self {
+        Self {
+            filter: "info".into(),
+            file: None,
+        }
+    }
+}
+
+impl Plugin for LogPlugin {
+    fn build(&self, ecs_builder: EcsBuilder<Incomplete>) -> EcsBuilder<Incomplete> {
+        let filter = std::env::var(LOG_ENV_VAR).unwrap_or_else(|_| self.filter.clone());
+        let (directives, invalid) = parse_filter(&filter);
+
+        let (file, file_error) = match &self.file {
+            Some(path) => match OpenOptions::new().create(true).append(true).open(path) {
+                Ok(file) => (Some(file), None),
+                Err(err) => (None, Some(format!("failed to open log file {path}: {err}"))),
+            },
+            None => (None, None),
+        };
+
+        let logger = EngineLogger {
+            max_level: directives.iter().map(|(_, level)| *level).max().unwrap_or(LevelFilter::Off),
+            directives,
+            file: file.map(Mutex::new),
+            start_time: Instant::now(),
+        };
+        let max_level = logger.max_level;
+
+        // Process-wide, so only the first Ecs installs it (see `Ecs`)
+        if log::set_boxed_logger(Box::new(logger)).is_ok() {
+            log::set_max_level(max_level);
+        }
+        // Reported once the logger is up
+        if let Some(file_error) = file_error {
+            log::error!("{file_error}");
+        }
+        for directive in invalid {
+            log::warn!("ignoring invalid log filter directive \"{directive}\"");
+        }
+
+        ecs_builder
+    }
+}
+
+struct EngineLogger {
+    // Sorted from least to most specific module path
+    directives: Vec<(Option<String>, LevelFilter)>,
+    max_level: LevelFilter,
+    file: Option<Mutex<File>>,
+    start_time: Instant,
+}
+
+impl EngineLogger {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.directives.iter().rev()
+            .find(|(module, _)| match module {
+                Some(module) => target == module || target.starts_with(&format!("{module}::")),
+                None => true,
+            })
+            .map_or(LevelFilter::Off, |(_, level)| *level)
+    }
+}
+
+impl Log for EngineLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = format!(
+            "[{:>9.3}s {:<5} {}] {}",
+            self.start_time.elapsed().as_secs_f32(),
+            record.level(),
+            record.target(),
+            record.args(),
+        );
+        eprintln!("{line}");
+
+        if let Some(file) = &self.file {
+            let mut file = file.lock().unwrap();
+            // Nowhere left to report a failing log sink to
+            let _ = writeln!(file, "{line}");
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            let _ = file.lock().unwrap().flush();
+        }
+    }
+}
+
+/// Parse a filter string into (module, level) directives sorted by specificity,
+/// along with any directives that couldn't be parsed
+fn parse_filter(filter: &str) -> (Vec<(Option<String>, LevelFilter)>, Vec<String>) {
+    let mut directives = Vec::new();
+    let mut invalid = Vec::new();
+
+    for directive in filter.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let parsed = match directive.split_once('=') {
+            Some((module, level)) => level.trim().parse().ok().map(|level| (Some(module.trim().to_string()), level)),
+            // A bare directive must be a level, so a typo like "inf" isn't mistaken for a module path
+            None => directive.parse().ok().map(|level| (None, level)),
+        };
+        match parsed {
+            Some(parsed) => directives.push(parsed),
+            None => invalid.push(directive.to_string()),
+        }
+    }
+
+    if !directives.iter().any(|(module, _)| module.is_none()) {
+        directives.push((None, LevelFilter::Error));
+    }
+    directives.sort_by_key(|(module, _)| module.as_ref().map_or(0, |m| m.len() + 1));
+    (directives, invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_directives_are_sorted_by_specificity() {
+        let (directives, invalid) = parse_filter("engine::render=debug, warn, engine=info");
+        assert!(invalid.is_empty());
+        assert_eq!(directives, vec![
+            (None, LevelFilter::Warn),
+            (Some("engine".into()), LevelFilter::Info),
+            (Some("engine::render".into()), LevelFilter::Debug),
+        ]);
+    }
+
+    #[test]
+    fn unknown_levels_are_invalid() {
+        let (directives, invalid) = parse_filter("inf,engine=loud,debug");
+        assert_eq!(invalid, vec!["inf".to_string(), "engine=loud".to_string()]);
+        assert_eq!(directives, vec![(None, LevelFilter::Debug)]);
+    }
+}
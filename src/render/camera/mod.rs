@@ -1,7 +1,9 @@
-use bevy_ecs::prelude::{Bundle, Component};
+use bevy_ecs::prelude::{Bundle, Component, IntoSystemConfigs};
 use glam::{Vec3, Mat4, Vec2};
+use serde::{Deserialize, Serialize};
+use winit::event::VirtualKeyCode;
 
-use crate::{common::Time, ecs::{Plugin, Startup, Update}};
+use crate::{common::{Time, TimePlugin}, ecs::{Plugin, Startup, Update}, input::InputPlugin, window::WindowRef};
 
 mod systems;
 
@@ -10,19 +12,37 @@ const PITCH: f32 = 0.0;
 const SPEED: f32 = 10.0;
 const ROT_SPEED: f32 = 50.0;
 const ZOOM: f32 = 45.0;
+const MOUSE_SENSITIVITY: f32 = 0.1;
 
 pub struct CameraPlugin;
 impl Plugin for CameraPlugin {
     fn build(&self, ecs_builder: crate::ecs::EcsBuilder<crate::ecs::Incomplete>) -> crate::ecs::EcsBuilder<crate::ecs::Incomplete> {
         ecs_builder
+            .add_action("move_forward", VirtualKeyCode::W)
+            .add_action("move_backward", VirtualKeyCode::S)
+            .add_action("move_left", VirtualKeyCode::A)
+            .add_action("move_right", VirtualKeyCode::D)
+            .add_action("look_up", VirtualKeyCode::Up)
+            .add_action("look_down", VirtualKeyCode::Down)
+            .add_action("look_left", VirtualKeyCode::Left)
+            .add_action("look_right", VirtualKeyCode::Right)
             .add_system(systems::spawn, Startup)
-            .add_system(systems::process_input, Update)
-            .add_system(systems::process_movement_input, Update)
-            .add_system(systems::process_rotation_input, Update)
+            .add_systems((
+                systems::process_input,
+                systems::process_movement_input,
+                systems::process_rotation_input,
+            ).chain(), Update)
+    }
+
+    fn dependencies(&self) -> Vec<Box<dyn Plugin>> {
+        // Camera controls read the InputStates resource and InputEvents, and move by Time::delta
+        vec![Box::new(InputPlugin), Box::new(TimePlugin)]
     }
 }
 
+#[derive(Clone, Default)]
 pub enum CameraMoveDirection {
+    #[default]
     None,
     Forward,
     Backward,
@@ -30,7 +50,8 @@ pub enum CameraMoveDirection {
     Right,
 }
 
-#[derive(Component)]
+#[derive(Component, Clone, Serialize, Deserialize)]
+#[serde(from = "CameraDesc", into = "CameraDesc")]
 pub struct Camera {
     pub position: Vec3,
     pub forward: Vec3,
@@ -42,14 +63,22 @@ pub struct Camera {
     pub zoom: f32,
 }
 
-#[derive(Component)]
+#[derive(Component, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CameraMovement {
+    #[serde(skip)]
     pub direction: CameraMoveDirection,
     pub speed: f32,
     pub constrain_pitch: bool,
     pub rotation_speed: f32,
+    /// Degrees turned per unit of raw mouse motion
+    pub mouse_sensitivity: f32,
 }
 
+/// Window a camera draws into. Cameras without this component draw into the primary window.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderTarget(pub WindowRef);
+
 #[derive(Bundle)]
 pub struct CameraBundle {
     pub camera: Camera,
@@ -73,6 +102,50 @@ impl Default for Camera {
     }
 }
 
+/// Serialized form of a Camera, leaving out the vectors derived from yaw and pitch
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct CameraDesc {
+    position: Vec3,
+    world_up: Vec3,
+    yaw: f32,
+    pitch: f32,
+    zoom: f32,
+}
+
+impl Default for CameraDesc {
+    fn default() -> Self {
+        Camera::default().into()
+    }
+}
+
+impl From<CameraDesc> for Camera {
+    fn from(desc: CameraDesc) -> Self {
+        let mut camera = Camera {
+            position: desc.position,
+            world_up: desc.world_up,
+            yaw: desc.yaw,
+            pitch: desc.pitch,
+            zoom: desc.zoom,
+            ..Default::default()
+        };
+        camera.update_vectors();
+        camera
+    }
+}
+
+impl From<Camera> for CameraDesc {
+    fn from(camera: Camera) -> Self {
+        CameraDesc {
+            position: camera.position,
+            world_up: camera.world_up,
+            yaw: camera.yaw,
+            pitch: camera.pitch,
+            zoom: camera.zoom,
+        }
+    }
+}
+
 impl Camera {
     pub fn get_view_mat(&self) -> Mat4 {
         Mat4::look_at_rh(self.position, self.position + self.forward, self.up)
@@ -105,16 +178,14 @@ impl Camera {
         }
     }
     
-    pub fn process_mouse_movement(&mut self,
-        mouse_pos: Vec2,
-        prev_mouse_pos: Vec2,
+    /// Turn by a raw mouse motion. The motion is already the distance moved this frame,
+    /// so unlike `process_rotation` it isn't scaled by the frame time.
+    pub fn process_mouse_motion(&mut self,
+        motion: Vec2,
         cam_move: &CameraMovement,
-        time: &Time,
     ) {
-        let mut x_offset = mouse_pos.x - prev_mouse_pos.x;
-        let mut y_offset = -(mouse_pos.y - prev_mouse_pos.y);
-        x_offset *= cam_move.rotation_speed * time.delta;
-        y_offset *= cam_move.rotation_speed * time.delta;
+        let x_offset = motion.x * cam_move.mouse_sensitivity;
+        let y_offset = -motion.y * cam_move.mouse_sensitivity;
         
         self.yaw += x_offset;
         self.pitch += y_offset;
@@ -189,6 +260,7 @@ impl Default for CameraMovement {
             speed: SPEED,
             constrain_pitch: false,
             rotation_speed: ROT_SPEED,
+            mouse_sensitivity: MOUSE_SENSITIVITY,
         }
     }
 }
@@ -1,13 +1,15 @@
-use bevy_ecs::{system::{Commands, Query, Res}, prelude::EventReader};
+use bevy_ecs::{system::{Commands, Query, Res}, prelude::{EventReader, With}};
 use glam::Vec3;
-use winit::event::VirtualKeyCode;
-
-use crate::{input::{InputEvent, InputStates}, common::Time};
+use crate::{input::{InputEvent, InputStates, KeyBindings}, common::Time};
 
 use super::{CameraBundle, Camera, CameraMovement};
 
 
-pub fn spawn(mut commands: Commands) {
+/// Spawn a default camera unless one has already been spawned (e.g. by a scene)
+pub fn spawn(mut commands: Commands, cam_qry: Query<(), With<Camera>>) {
+    if !cam_qry.is_empty() {
+        return;
+    }
     commands.spawn(CameraBundle {
         camera: Camera::from_position(0.0, 0.0, 3.0),
         ..Default::default()
@@ -15,12 +17,10 @@ pub fn spawn(mut commands: Commands) {
 }
 
 pub fn process_input(
-    mut cam_qry: Query<(&mut Camera, &mut CameraMovement)>,
+    mut cam_qry: Query<(&mut Camera, &CameraMovement)>,
     mut input_rdr: EventReader<InputEvent>,
-    time: Res<Time>,
-    states: Res<InputStates>,
 ) {
-    let (mut cam, mut cam_move) = cam_qry.single_mut();
+    let (mut cam, cam_move) = cam_qry.single_mut();
     
     for evt in input_rdr.iter() {
         let input = &evt.0;
@@ -29,12 +29,10 @@ pub fn process_input(
         if input.mouse_scroll_delta != 0.0 {
             cam.process_mouse_scroll(input.mouse_scroll_delta);
         }
-        // turning
-        /*
-        if let (Some(mouse_pos), Some(prev_mouse_pos)) = (input.mouse_pos, input.prev_mouse_pos) {
-            cam.process_mouse_movement(mouse_pos, prev_mouse_pos, &cam_move, &time);
+        // turning, only reported while the cursor is grabbed
+        if let Some(motion) = input.mouse_motion {
+            cam.process_mouse_motion(motion, cam_move);
         }
-        */
     }
 }
 
@@ -42,21 +40,21 @@ pub fn process_movement_input(
     mut cam_qry: Query<(&mut Camera, &CameraMovement)>,
     time: Res<Time>,
     states: Res<InputStates>,
+    bindings: Res<KeyBindings>,
 ) {
     let (mut cam, movement) = cam_qry.single_mut();
     
     let mut local_move_dir = Vec3::ZERO;
-    let keyholds = &states.keyholds;
-    if keyholds.contains(&VirtualKeyCode::W) {
+    if bindings.is_held("move_forward", &states) {
         local_move_dir.z -= 1.0;
     }
-    if keyholds.contains(&VirtualKeyCode::S) {
+    if bindings.is_held("move_backward", &states) {
         local_move_dir.z += 1.0;
     }
-    if keyholds.contains(&VirtualKeyCode::A) {
+    if bindings.is_held("move_left", &states) {
         local_move_dir.x -= 1.0;
     }
-    if keyholds.contains(&VirtualKeyCode::D) {
+    if bindings.is_held("move_right", &states) {
         local_move_dir.x += 1.0;
     }
     
@@ -67,22 +65,22 @@ pub fn process_rotation_input(
     mut cam_qry: Query<(&mut Camera, &CameraMovement)>,
     time: Res<Time>,
     states: Res<InputStates>,
+    bindings: Res<KeyBindings>,
 ) {
     let (mut cam, movement) = cam_qry.single_mut();
     
     let mut pitch = 0.0;
     let mut yaw = 0.0;
-    let keyholds = &states.keyholds;
-    if keyholds.contains(&VirtualKeyCode::Up) {
+    if bindings.is_held("look_up", &states) {
         pitch += 1.0;
     }
-    if keyholds.contains(&VirtualKeyCode::Down) {
+    if bindings.is_held("look_down", &states) {
         pitch -= 1.0;
     }
-    if keyholds.contains(&VirtualKeyCode::Left) {
+    if bindings.is_held("look_left", &states) {
         yaw -= 1.0;
     }
-    if keyholds.contains(&VirtualKeyCode::Right) {
+    if bindings.is_held("look_right", &states) {
         yaw += 1.0;
     }
     
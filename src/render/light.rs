@@ -0,0 +1,57 @@
+use bevy_ecs::prelude::Component;
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+/// Light shining from infinitely far away in a single direction, like the sun
+#[derive(Component, Clone, Debug, Serialize, Deserialize)]
+pub struct DirectionalLight {
+    pub direction: Vec3,
+    pub ambient: Vec3,
+    pub diffuse: Vec3,
+    pub specular: Vec3,
+}
+
+/// Light radiating in all directions from the entity's GlobalTransform
+#[derive(Component, Clone, Debug, Serialize, Deserialize)]
+pub struct PointLight {
+    pub ambient: Vec3,
+    pub diffuse: Vec3,
+    pub specular: Vec3,
+    #[serde(default)]
+    pub attenuation: Attenuation,
+}
+
+/// Cone of light. Position and direction are in view space, so the default follows the camera like a flashlight.
+#[derive(Component, Clone, Debug, Serialize, Deserialize)]
+pub struct SpotLight {
+    pub position: Vec3,
+    pub direction: Vec3,
+    /// Angle in degrees at which the light starts to fade out
+    pub cutoff_angle: f32,
+    /// Angle in degrees beyond which there is no light at all
+    pub outer_cutoff_angle: f32,
+    pub ambient: Vec3,
+    pub diffuse: Vec3,
+    pub specular: Vec3,
+    #[serde(default)]
+    pub attenuation: Attenuation,
+}
+
+/// Coefficients of the constant, linear and quadratic terms of a light's falloff with distance
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Default for Attenuation {
+    fn default() -> Self {
+        // roughly a range of 50 units
+        Self {
+            constant: 1.0,
+            linear: 0.09,
+            quadratic: 0.032,
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use bevy_ecs::prelude::Component;
+use serde::{Deserialize, Serialize};
+
+/// Surface properties used when drawing an entity's Model with the lit shader
+#[derive(Component, Clone, Debug, Serialize, Deserialize)]
+pub struct Material {
+    #[serde(default = "Material::default_shininess")]
+    pub shininess: f32,
+    /// Replaces the diffuse maps of the model's own materials
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diffuse: Option<String>,
+    /// Replaces the specular maps of the model's own materials
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub specular: Option<String>,
+}
+
+impl Material {
+    fn default_shininess() -> f32 {
+        64.0
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            shininess: Self::default_shininess(),
+            diffuse: None,
+            specular: None,
+        }
+    }
+}
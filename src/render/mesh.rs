@@ -21,7 +21,7 @@ pub struct Texture {
     pub filepath: String,
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub enum TextureType {
     Diffuse,
     Specular,
@@ -109,6 +109,12 @@ impl Mesh {
         }
     }
     
+    /// Replace all textures of the same type with `tex`
+    pub fn set_texture(&mut self, tex: Texture) {
+        self.textures.retain(|t| t.tex_type != tex.tex_type);
+        self.textures.push(tex);
+    }
+    
     pub unsafe fn draw(&self, shader: &Shader) {
         let mut diffuse_num = 1;
         let mut specular_num = 1;
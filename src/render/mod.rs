@@ -1,14 +1,18 @@
 use std::ffi::CStr;
 
-use bevy_ecs::system::{Resource, Commands, Res};
+use bevy_ecs::{prelude::IntoSystemConfigs, system::{Resource, Commands, Res}};
 
-use crate::{window::Window, ecs::{Plugin, EcsBuilderState, EcsBuilder, Incomplete, Render, StartupSingleThreaded}};
+use crate::{window::Window, common::TimePlugin, tasks::TaskPoolPlugin, transform::TransformPlugin, ecs::{Plugin, EcsBuilderState, EcsBuilder, Incomplete, Render, StartupSingleThreaded}};
 
 use self::{shader::Shader, model::Model, camera::CameraPlugin};
 
-mod camera;
-mod mesh;
-mod model;
+pub use self::mesh::TextureType;
+
+pub mod camera;
+pub mod light;
+pub mod material;
+pub(crate) mod mesh;
+pub mod model;
 mod shader;
 mod utils;
 mod systems;
@@ -19,7 +23,12 @@ impl Plugin for RenderPlugin {
         ecs_builder
             .add_plugin(CameraPlugin)
             .add_system(systems::init, StartupSingleThreaded)
-            .add_system(systems::draw, Render)
+            .add_systems((systems::upload_loaded_models, systems::draw).chain(), Render)
+    }
+
+    fn dependencies(&self) -> Vec<Box<dyn Plugin>> {
+        // The lit shader is animated with Time::current, and models are drawn at their GlobalTransform
+        vec![Box::new(TimePlugin), Box::new(TransformPlugin), Box::new(TaskPoolPlugin::default())]
     }
 }
 
@@ -30,14 +39,4 @@ pub struct RenderObjs {
     lit_shader: Shader,
     unlit_shader: Shader,
     num_elems: u32,
-    diffuse_map: u32,
-    specular_map: u32,
-    emission_map: u32,
-    model: Model,
-}
-
-pub fn resize(width: i32, height: i32) {
-    unsafe {
-        gl::Viewport(0, 0, width, height);
-    }
 }
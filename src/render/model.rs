@@ -1,20 +1,65 @@
-use std::path::Path;
+use std::{collections::HashMap, path::Path};
 
+use bevy_ecs::prelude::Component;
 use glam::{Vec3, Vec2};
+use image::DynamicImage;
+
+use crate::{error::EngineError, tasks::{ComputeTasks, IoTasks, PendingTask}};
 
 use super::{mesh::{Mesh, Texture, Vertex, TextureType}, shader::Shader, utils};
 
 
+#[derive(Component)]
 pub struct Model {
     meshes: Vec<Mesh>,
+    filepath: String,
     directory: String,
     textures_loaded: Vec<Texture>, // stores all textures loaded so far to make sure textures aren't loaded more than once
 }
 
 impl Model {
-    pub fn new(filepath: &str) -> Self {
-        let (meshes, directory, textures_loaded) = Self::load_model(filepath);
-        Self { meshes, directory: directory.into(), textures_loaded }
+    pub fn new(filepath: &str) -> Result<Self, EngineError> {
+        Ok(Self::upload(ModelData::load(filepath)?))
+    }
+    
+    /// Create the GL buffers and textures of a loaded model.
+    /// Must run on the thread that owns the GL context.
+    pub fn upload(data: ModelData) -> Self {
+        let textures_loaded: Vec<Texture> = data.images.iter()
+            .map(|(filepath, img)| Texture {
+                id: unsafe { utils::upload_texture(img, filepath) },
+                tex_type: TextureType::Diffuse,
+                filepath: filepath.clone(),
+            })
+            .collect();
+        
+        let meshes = data.meshes.into_iter()
+            .map(|mesh| {
+                let textures = mesh.textures.iter()
+                    .map(|(filepath, tex_type)| {
+                        let tex = textures_loaded.iter().find(|tex| tex.filepath == *filepath).unwrap();
+                        Texture { tex_type: tex_type.clone(), ..tex.clone() }
+                    })
+                    .collect();
+                let indices = (0..(mesh.vertices.len() as u32)).collect();
+                unsafe { Mesh::new(mesh.vertices, indices, textures) }
+            })
+            .collect();
+        
+        Self { meshes, filepath: data.filepath, directory: data.directory, textures_loaded }
+    }
+    
+    pub fn filepath(&self) -> &str {
+        &self.filepath
+    }
+    
+    /// Replace the textures of the given type on every mesh with the texture at `filepath`
+    pub fn override_texture(&mut self, filepath: &str, tex_type: TextureType) -> Result<(), EngineError> {
+        let tex = Self::load_material_texture(filepath, tex_type, &mut self.textures_loaded)?;
+        for mesh in &mut self.meshes {
+            mesh.set_texture(tex.clone());
+        }
+        Ok(())
     }
     
     pub fn draw(&self, shader: &Shader) {
@@ -23,21 +68,158 @@ impl Model {
         }
     }
     
-    fn load_model(filepath: &str) -> (Vec<Mesh>, &str, Vec<Texture>) {
-        // load file
+    fn load_material_texture(
+        filepath: &str,
+        tex_type: TextureType,
+        textures_loaded: &mut Vec<Texture>,
+    ) -> Result<Texture, EngineError> {
+        let tex = textures_loaded.iter().find(|tex| tex.filepath == filepath);
+        if let Some(tex) = tex {
+            return Ok(Texture { tex_type, ..tex.clone() });
+        }
+        
+        let tex = Texture {
+            id: unsafe { utils::load_texture(filepath)? },
+            tex_type,
+            filepath: filepath.into(),
+        };
+        textures_loaded.push(tex.clone());
+        Ok(tex)
+    }
+}
+
+/// A Model being read and decoded in the background.
+/// It's uploaded and replaced by the Model in the Render schedule once it has finished loading.
+#[derive(Component)]
+pub struct LoadingModel {
+    filepath: String,
+    task: PendingTask<Result<ModelData, EngineError>>,
+}
+
+impl LoadingModel {
+    /// Start loading the model at `filepath`, replacing its textures with `overrides` once it's parsed.
+    /// Files are read on the IoTasks pool, and parsed and decoded on the ComputeTasks pool.
+    pub fn spawn(io: IoTasks, compute: &ComputeTasks, filepath: &str, overrides: Vec<(String, TextureType)>) -> Self {
+        let path = filepath.to_string();
+        let task = PendingTask::spawn(compute, async move {
+            let (_, obj) = read_files(io, vec![path.clone()]).await.pop().unwrap();
+            let obj = obj?;
+            // A missing material library is reported by the parser
+            let mtls = read_files(io, ModelData::material_libs(&path, &obj)).await.into_iter()
+                .filter_map(|(path, source)| Some((path, source.ok()?)))
+                .collect();
+
+            let mut data = ModelData::parse(&path, &obj, &mtls)?;
+            for (tex_path, tex_type) in overrides {
+                data.replace_texture(&tex_path, tex_type);
+            }
+            let errors: Vec<EngineError> = read_files(io, data.missing_textures()).await.into_iter()
+                .filter_map(|(tex_path, bytes)| bytes.and_then(|bytes| data.decode_texture(&tex_path, &bytes)).err())
+                .collect();
+            if !errors.is_empty() {
+                return Err(EngineError::aggregate(format!("model {path}"), errors));
+            }
+            Ok(data)
+        });
+        Self { filepath: filepath.into(), task }
+    }
+    
+    pub fn filepath(&self) -> &str {
+        &self.filepath
+    }
+    
+    /// Take the loaded model data (or the error that stopped it) if loading has finished
+    pub fn poll(&mut self) -> Option<Result<ModelData, EngineError>> {
+        self.task.poll()
+    }
+}
+
+/// Read the files at `paths` at the same time on the IoTasks pool
+async fn read_files(io: IoTasks, paths: Vec<String>) -> Vec<(String, Result<Vec<u8>, EngineError>)> {
+    let tasks: Vec<_> = paths.into_iter()
+        .map(|path| io.spawn(async move {
+            let bytes = utils::read_file(&path);
+            (path, bytes)
+        }))
+        .collect();
+    let mut files = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        files.push(task.await);
+    }
+    files
+}
+
+/// A model read from disk with its textures decoded, but not uploaded yet.
+/// Loading one doesn't touch GL, so it can be done on a background thread.
+pub struct ModelData {
+    filepath: String,
+    directory: String,
+    meshes: Vec<MeshData>,
+    images: Vec<(String, DynamicImage)>, // every texture used by the meshes, decoded once
+}
+
+struct MeshData {
+    vertices: Vec<Vertex>,
+    textures: Vec<(String, TextureType)>,
+}
+
+impl ModelData {
+    /// Read, parse and decode a model on the current thread
+    pub fn load(filepath: &str) -> Result<Self, EngineError> {
+        let obj = utils::read_file(filepath)?;
+        let mtls = Self::material_libs(filepath, &obj).into_iter()
+            .filter_map(|path| Some((path.clone(), utils::read_file(&path).ok()?)))
+            .collect();
+
+        let mut data = Self::parse(filepath, &obj, &mtls)?;
+        let errors: Vec<EngineError> = data.missing_textures().into_iter()
+            .filter_map(|tex_path| utils::read_file(&tex_path).and_then(|bytes| data.decode_texture(&tex_path, &bytes)).err())
+            .collect();
+        if !errors.is_empty() {
+            return Err(EngineError::aggregate(format!("model {filepath}"), errors));
+        }
+        Ok(data)
+    }
+
+    /// Paths of the material libraries used by an OBJ file, relative to the working directory
+    fn material_libs(filepath: &str, obj: &[u8]) -> Vec<String> {
+        let directory = Path::new(filepath).parent().unwrap_or_else(|| Path::new(""));
+        String::from_utf8_lossy(obj).lines()
+            .filter(|line| line.split_whitespace().next() == Some("mtllib"))
+            // File names can contain spaces
+            .filter_map(|line| line.trim().split_once(' '))
+            .map(|(_, name)| directory.join(name.trim()).to_string_lossy().into_owned())
+            .collect()
+    }
+
+    /// Parse an OBJ file, given the contents of the material libraries it uses.
+    /// Textures are only listed on the meshes, see `missing_textures`.
+    fn parse(filepath: &str, obj: &[u8], mtls: &HashMap<String, Vec<u8>>) -> Result<Self, EngineError> {
         let path = Path::new(filepath);
-        let directory = path.parent().unwrap_or_else(|| Path::new("")).to_str().unwrap().into();
-        let obj = tobj::load_obj(path, &tobj::LoadOptions {
+        let directory: String = path.parent().unwrap_or_else(|| Path::new("")).to_str().unwrap().into();
+        let load_options = tobj::LoadOptions {
             single_index: true,
             ..Default::default()
+        };
+        let obj = tobj::load_obj_buf(&mut &obj[..], &load_options, |mtl_path| {
+            let mtl_path = Path::new(&directory).join(mtl_path).to_string_lossy().into_owned();
+            match mtls.get(&mtl_path) {
+                Some(source) => tobj::load_mtl_buf(&mut &source[..]),
+                None => Err(tobj::LoadError::OpenFileFailed),
+            }
         });
         
-        let (models, materials) = obj.expect("failed to load OBJ file");
-        let materials = materials.expect("failed to load MTL file");
+        let (models, materials) = obj
+            .map_err(|source| EngineError::Model { path: filepath.into(), source })?;
+        let materials = materials
+            .map_err(|source| EngineError::Material { path: filepath.into(), source })?;
         
-        let mut meshes = Vec::new();
-        let mut textures_loaded = Vec::new();
-
+        let mut data = Self {
+            filepath: filepath.into(),
+            directory: directory.clone(),
+            meshes: Vec::new(),
+            images: Vec::new(),
+        };
 
         for model in models {
             let mesh = &model.mesh;
@@ -79,16 +261,12 @@ impl Model {
                 
                 // diffuse map
                 if let Some(filename) = &material.diffuse_texture {
-                    let filepath = format!("{}/{}", directory, filename);
-                    let tex = Self::load_material_texture(&filepath, TextureType::Diffuse, &mut textures_loaded);
-                    textures.push(tex);
+                    textures.push((format!("{}/{}", directory, filename), TextureType::Diffuse));
                 }
                 
                 // specular map
                 if let Some(filename) = &material.specular_texture {
-                    let filepath = format!("{}/{}", directory, filename);
-                    let tex = Self::load_material_texture(&filepath, TextureType::Specular, &mut textures_loaded);
-                    textures.push(tex);
+                    textures.push((format!("{}/{}", directory, filename), TextureType::Specular));
                 }
                 
                 // normal map
@@ -99,26 +277,146 @@ impl Model {
                 // NOTE: no height maps
             }
             
-            let indices = (0..(vertices.len() as u32)).collect();
-            let mesh = unsafe { Mesh::new(vertices, indices, textures) };
-            meshes.push(mesh);
+            data.meshes.push(MeshData { vertices, textures });
         }
         
-        (meshes, directory, textures_loaded)
+        Ok(data)
     }
     
-    fn load_material_texture(filepath: &str, tex_type: TextureType, textures_loaded: &mut Vec<Texture>) -> Texture {
-        let tex = textures_loaded.iter().find(|tex| tex.filepath == filepath);
-        if let Some(tex) = tex {
-            return tex.clone();
+    pub fn filepath(&self) -> &str {
+        &self.filepath
+    }
+    
+    /// Replace the textures of the given type on every mesh with the texture at `filepath`.
+    /// The texture still has to be decoded, see `missing_textures`.
+    fn replace_texture(&mut self, filepath: &str, tex_type: TextureType) {
+        for mesh in &mut self.meshes {
+            mesh.textures.retain(|(_, t)| *t != tex_type);
+            mesh.textures.push((filepath.into(), tex_type.clone()));
         }
-        
-        let tex = Texture {
-            id: unsafe { utils::load_texture(filepath) },
-            tex_type,
-            filepath: filepath.into(),
+    }
+
+    /// Textures used by the meshes that haven't been decoded yet
+    fn missing_textures(&self) -> Vec<String> {
+        let mut missing: Vec<String> = Vec::new();
+        for (path, _) in self.meshes.iter().flat_map(|mesh| &mesh.textures) {
+            if !self.images.iter().any(|(decoded, _)| decoded == path) && !missing.contains(path) {
+                missing.push(path.clone());
+            }
+        }
+        missing
+    }
+    
+    fn decode_texture(&mut self, filepath: &str, bytes: &[u8]) -> Result<(), EngineError> {
+        if !self.images.iter().any(|(path, _)| path == filepath) {
+            self.images.push((filepath.into(), utils::decode_texture(filepath, bytes)?));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread, time::{Duration, Instant}};
+
+    use bevy_ecs::{schedule::Schedule, world::World};
+
+    use super::*;
+    use crate::{
+        ecs::EcsBuilder,
+        error::{AssetErrors, StartupAssetLoads},
+        render::systems::upload_loaded_models,
+        tasks::TaskPoolPlugin,
+    };
+
+    /// Write a triangle with a diffuse and a specular map to a fresh directory,
+    /// returning the path of its OBJ file
+    fn write_triangle(name: &str, with_textures: bool) -> String {
+        let dir = std::env::temp_dir().join(format!("engine_model_test_{}_{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("triangle.obj"), "mtllib triangle.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nusemtl flat\nf 1/1 2/2 3/3\n").unwrap();
+        fs::write(dir.join("triangle.mtl"), "newmtl flat\nmap_Kd diffuse.png\nmap_Ks specular.png\n").unwrap();
+        if with_textures {
+            image::RgbImage::new(2, 2).save(dir.join("diffuse.png")).unwrap();
+            image::RgbImage::new(2, 2).save(dir.join("specular.png")).unwrap();
+        }
+        dir.join("triangle.obj").to_string_lossy().into_owned()
+    }
+
+    fn task_pool_world() -> World {
+        EcsBuilder::new()
+            .add_plugin(TaskPoolPlugin::default())
+            .set_runner(|world: World| world)
+            .build()
+            .run()
+    }
+
+    fn load_in_background(path: &str) -> Result<ModelData, EngineError> {
+        let world = task_pool_world();
+        let mut loading = LoadingModel::spawn(*world.resource::<IoTasks>(), world.resource::<ComputeTasks>(), path, Vec::new());
+
+        let start = Instant::now();
+        loop {
+            if let Some(result) = loading.poll() {
+                return result;
+            }
+            assert!(start.elapsed() < Duration::from_secs(10), "loading {path} didn't finish");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn model_loads_in_the_background() {
+        let data = load_in_background(&write_triangle("ok", true)).unwrap();
+
+        assert_eq!(data.meshes.len(), 1);
+        assert_eq!(data.meshes[0].vertices.len(), 3);
+        assert_eq!(data.meshes[0].textures.len(), 2);
+        assert_eq!(data.images.len(), 2);
+    }
+
+    #[test]
+    fn missing_textures_are_reported_together() {
+        let Err(err) = load_in_background(&write_triangle("missing_textures", false)) else {
+            panic!("loaded a model without its textures");
         };
-        textures_loaded.push(tex.clone());
-        tex
+
+        let EngineError::Asset { errors, .. } = &err else {
+            panic!("expected both textures to be reported, got {err}");
+        };
+        let mut paths: Vec<&str> = errors.iter()
+            .map(|err| match err {
+                EngineError::Io { path, .. } => path.rsplit('/').next().unwrap(),
+                other => panic!("unexpected error {other}"),
+            })
+            .collect();
+        paths.sort();
+        assert_eq!(paths, ["diffuse.png", "specular.png"]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn startup_load_failures_are_reported_once_all_have_finished() {
+        let mut world = task_pool_world();
+        let path = write_triangle("startup", false);
+        let pending = (0..2)
+            .map(|_| {
+                let loading = LoadingModel::spawn(*world.resource::<IoTasks>(), world.resource::<ComputeTasks>(), &path, Vec::new());
+                world.spawn(loading).id()
+            })
+            .collect();
+        world.insert_resource(StartupAssetLoads { pending, errors: Vec::new() });
+        let mut schedule = Schedule::new();
+        schedule.add_system(upload_loaded_models);
+
+        let start = Instant::now();
+        while world.contains_resource::<StartupAssetLoads>() {
+            assert!(world.resource::<AssetErrors>().0.is_empty(), "reported before every load has finished");
+            assert!(start.elapsed() < Duration::from_secs(10), "loading {path} didn't finish");
+            thread::sleep(Duration::from_millis(1));
+            schedule.run(&mut world);
+        }
+
+        assert_eq!(world.resource::<AssetErrors>().0.len(), 2);
+    }
+}
@@ -1,86 +1,112 @@
-use std::{ffi::CString, fs::File, io::Read, ptr};
+use std::{ffi::CString, fs::File, io::{self, Read}, ptr};
 
-use gl::types::{GLint, GLchar};
+use gl::types::{GLint, GLchar, GLenum};
 use glam::{Mat4, Mat3};
 
+use crate::error::EngineError;
+
 pub struct Shader {
     pub id: u32
 }
 
 impl Shader {
-    pub fn new(vert_path: &str, frag_path: &str) -> Shader {
-        // read vertex shader source code from filesystem
-        let mut vert_file = File::open(vert_path)
-            .unwrap_or_else(|_| panic!("failed to open {}", vert_path));
-        let mut vert_src = String::new();
-        vert_file.read_to_string(&mut vert_src)
-            .expect("failed to read vertex shader");
-        let vert_src = CString::new(vert_src.as_bytes()).unwrap();
-        
-        // read fragment shader source code from filesystem
-        let mut frag_file = File::open(frag_path)
-            .unwrap_or_else(|_| panic!("failed to open {}", frag_path));
-        let mut frag_src = String::new();
-        frag_file.read_to_string(&mut frag_src)
-            .expect("failed to read fragment shader");
-        let frag_src = CString::new(frag_src.as_bytes()).unwrap();
+    pub fn new(vert_path: &str, frag_path: &str) -> Result<Shader, EngineError> {
+        // read shader source code from filesystem
+        let (vert_src, frag_src) = match (Shader::read_source(vert_path), Shader::read_source(frag_path)) {
+            (Ok(vert_src), Ok(frag_src)) => (vert_src, frag_src),
+            (vert_src, frag_src) => {
+                let errors = [vert_src.err(), frag_src.err()].into_iter().flatten().collect();
+                return Err(EngineError::aggregate(format!("shader program ({vert_path}, {frag_path})"), errors));
+            },
+        };
         
         // compile shaders
-        let shader_program = unsafe {
+        unsafe {
             // vertex shader
-            let vert_shader = gl::CreateShader(gl::VERTEX_SHADER);
-            gl::ShaderSource(vert_shader, 1, &vert_src.as_ptr(), ptr::null());
-            gl::CompileShader(vert_shader);
-            Shader::check_compile_errors(vert_shader, "VERTEX");
+            let vert_shader = Shader::compile(gl::VERTEX_SHADER, &vert_src, vert_path)?;
             
             // fragment shader
-            let frag_shader = gl::CreateShader(gl::FRAGMENT_SHADER);
-            gl::ShaderSource(frag_shader, 1, &frag_src.as_ptr(), ptr::null());
-            gl::CompileShader(frag_shader);
-            Shader::check_compile_errors(frag_shader, "FRAGMENT");
+            let frag_shader = match Shader::compile(gl::FRAGMENT_SHADER, &frag_src, frag_path) {
+                Ok(frag_shader) => frag_shader,
+                Err(err) => {
+                    gl::DeleteShader(vert_shader);
+                    return Err(err);
+                },
+            };
             
             // shader program
             let shader_program = gl::CreateProgram();
             gl::AttachShader(shader_program, vert_shader);
             gl::AttachShader(shader_program, frag_shader);
             gl::LinkProgram(shader_program);
-            Shader::check_link_errors(shader_program);
+            let link_result = Shader::check_link_errors(shader_program);
             
             // cleanup
             gl::DeleteShader(vert_shader);
             gl::DeleteShader(frag_shader);
             
-            shader_program
-        };
-        
-        Shader { id: shader_program }
+            if let Err(log) = link_result {
+                gl::DeleteProgram(shader_program);
+                return Err(EngineError::ShaderLink {
+                    vert_path: vert_path.into(),
+                    frag_path: frag_path.into(),
+                    log,
+                });
+            }
+            
+            Ok(Shader { id: shader_program })
+        }
+    }
+    
+    fn read_source(path: &str) -> Result<CString, EngineError> {
+        let mut src = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut src))
+            .map_err(|source| EngineError::Io { path: path.into(), source })?;
+        CString::new(src.as_bytes()).map_err(|err| EngineError::Io {
+            path: path.into(),
+            source: io::Error::new(io::ErrorKind::InvalidData, err),
+        })
+    }
+    
+    unsafe fn compile(shader_type: GLenum, src: &CString, path: &str) -> Result<u32, EngineError> {
+        let shader = gl::CreateShader(shader_type);
+        gl::ShaderSource(shader, 1, &src.as_ptr(), ptr::null());
+        gl::CompileShader(shader);
+        if let Err(log) = Shader::check_compile_errors(shader) {
+            gl::DeleteShader(shader);
+            return Err(EngineError::ShaderCompile { path: path.into(), log });
+        }
+        Ok(shader)
     }
 
-    unsafe fn check_compile_errors(shader: u32, shader_type: &str) {
+    unsafe fn check_compile_errors(shader: u32) -> Result<(), String> {
         let mut success = gl::FALSE as GLint;
         gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
         if success != gl::TRUE as GLint {
             let mut info_log = Vec::with_capacity(512);
             info_log.resize(512 - 1, 0); // subtract 1 to skip the trailing null character
             gl::GetShaderInfoLog(shader, 512, ptr::null_mut(), info_log.as_mut_ptr() as *mut GLchar);
-            println!(
-                "shader failed to compile: {}\n--------------------\n{}",
-                shader_type,
-                std::str::from_utf8(&info_log).unwrap()
-            );
+            return Err(Shader::info_log_to_string(&info_log));
         }
-        
+        Ok(())
     }
     
-    unsafe fn check_link_errors(shader_program: u32) {
+    unsafe fn check_link_errors(shader_program: u32) -> Result<(), String> {
         let mut success = gl::FALSE as GLint;
         gl::GetProgramiv(shader_program, gl::LINK_STATUS, &mut success);
         if success != gl::TRUE as GLint {
             let mut info_log = Vec::with_capacity(512);
             info_log.resize(512 - 1, 0);
             gl::GetProgramInfoLog(shader_program, 512, ptr::null_mut(), info_log.as_mut_ptr() as *mut GLchar);
-            println!("shader program failed to link\n--------------------\n{}", std::str::from_utf8(&info_log).unwrap());
+            return Err(Shader::info_log_to_string(&info_log));
         }
+        Ok(())
+    }
+    
+    fn info_log_to_string(info_log: &[u8]) -> String {
+        let len = info_log.iter().position(|c| *c == 0).unwrap_or(info_log.len());
+        String::from_utf8_lossy(&info_log[..len]).trim_end().to_string()
     }
 
     pub unsafe fn activate(&self) {
@@ -1,14 +1,25 @@
 use std::{ptr, mem::size_of, ffi::c_void, path::Path};
 
-use bevy_ecs::{system::{Query, Res, Commands}};
+use bevy_ecs::{prelude::Entity, system::{Query, Res, ResMut, Commands, NonSend, SystemParam}};
 use gl::types::{GLfloat, GLsizei, GLsizeiptr};
 use glam::{Vec3, Mat4, Mat3};
 
-use crate::{common::Time, window::{WindowInfo, self}};
+use crate::{common::Time, error::{AssetErrors, StartupAssetLoads, StartupErrors, report_startup_asset_errors}, window::{WindowInfo, Window, WindowRef, SecondaryWindow, self}, transform::GlobalTransform};
 
-use super::{utils::load_texture, Model, camera::Camera, RenderObjs, shader::Shader};
+use super::{
+    Model,
+    model::LoadingModel,
+    camera::{Camera, RenderTarget},
+    light::{DirectionalLight, PointLight, SpotLight, Attenuation},
+    material::Material,
+    RenderObjs,
+    shader::Shader,
+};
 
-pub fn init(mut commands: Commands) {
+/// Size of the point light array in shaders/lit.frag
+const NR_POINT_LIGHTS: usize = 4;
+
+pub fn init(mut commands: Commands, mut errors: ResMut<StartupErrors>) {
     let (lit_cube_vao, unlit_cube_vao, num_elems) = unsafe {
         let vertices: [f32; 288] = [
             // positions      // normals        // texture coords
@@ -147,12 +158,6 @@ pub fn init(mut commands: Commands) {
         (lit_cube_vao, unlit_cube_vao, indices.len() as u32)
     };
     
-    let diffuse_map = unsafe { load_texture("assets/container2.png") };
-    
-    let specular_map = unsafe { load_texture("assets/container2_specular.png") };
-    
-    let emission_map = unsafe { load_texture("assets/matrix.jpg") };
-    
     let lit_shader = Shader::new(
         "shaders/lit.vert",
         "shaders/lit.frag",
@@ -164,7 +169,14 @@ pub fn init(mut commands: Commands) {
         "shaders/unlit.frag",
     );
     
-    let model = Model::new("assets/backpack/backpack.obj");
+    let (lit_shader, unlit_shader) = match (lit_shader, unlit_shader) {
+        (Ok(lit_shader), Ok(unlit_shader)) => (lit_shader, unlit_shader),
+        (lit_shader, unlit_shader) => {
+            errors.0.extend(lit_shader.err());
+            errors.0.extend(unlit_shader.err());
+            return;
+        },
+    };
     
     commands.insert_resource(RenderObjs {
         lit_cube_vao,
@@ -174,22 +186,96 @@ pub fn init(mut commands: Commands) {
         unlit_shader,
 
         num_elems,
-        diffuse_map,
-        specular_map,
-        emission_map,
-        model,
     });
 }
 
 
+/// Upload the models that have finished loading in the background, replacing their LoadingModel.
+/// Failures of the loads started during startup are reported together once they have all finished.
+pub fn upload_loaded_models(
+    mut commands: Commands,
+    mut loading_qry: Query<(Entity, &mut LoadingModel)>,
+    mut asset_errors: ResMut<AssetErrors>,
+    mut startup_loads: Option<ResMut<StartupAssetLoads>>,
+) {
+    for (entity, mut loading) in &mut loading_qry {
+        let Some(result) = loading.poll() else { continue };
+        let mut entity_commands = commands.entity(entity);
+        entity_commands.remove::<LoadingModel>();
+        match result {
+            Ok(data) => {
+                log::info!("loaded model {}", data.filepath());
+                entity_commands.insert(Model::upload(data));
+            },
+            Err(err) => match &mut startup_loads {
+                Some(startup_loads) if startup_loads.pending.contains(&entity) => startup_loads.errors.push(err),
+                _ => {
+                    log::error!("failed to load the model of {entity:?}: {err}");
+                    asset_errors.0.push(err);
+                },
+            },
+        }
+        if let Some(startup_loads) = &mut startup_loads {
+            startup_loads.pending.remove(&entity);
+        }
+    }
+
+    let Some(startup_loads) = &mut startup_loads else { return };
+    // Entities despawned while loading won't finish
+    startup_loads.pending.retain(|&entity| loading_qry.contains(entity));
+    if startup_loads.pending.is_empty() {
+        report_startup_asset_errors(&startup_loads.errors);
+        asset_errors.0.append(&mut startup_loads.errors);
+        commands.remove_resource::<StartupAssetLoads>();
+    }
+}
+
+/// Draw every window with the cameras targeting it
 pub fn draw(
-    cam_qry: Query<&Camera>,
+    cam_qry: Query<(&Camera, Option<&RenderTarget>)>,
+    model_qry: Query<(&Model, &GlobalTransform, Option<&Material>)>,
+    lights: Lights,
     render_objs: Res<RenderObjs>,
-    window_info: Res<WindowInfo>,
+    windows: Windows,
     time: Res<Time>,
 ) {
-    let cam = cam_qry.single();
-    
+    let Windows { window_info, secondary_qry, window } = windows;
+
+    // Without a Window (e.g. a custom runner) whatever surface is current is drawn as the primary window
+    let mut targets = vec![(WindowRef::Primary, window_info.clone())];
+    if window.is_some() {
+        targets.extend(secondary_qry.iter()
+            .map(|(entity, secondary)| (WindowRef::Entity(entity), secondary.info.clone())));
+    }
+
+    for (target, target_info) in &targets {
+        if window.as_ref().is_some_and(|window| !window.make_current(*target)) {
+            continue;
+        }
+        // The sizes follow the WindowResized events, see `window::update_window_sizes`
+        unsafe {
+            gl::Viewport(0, 0, target_info.width as i32, target_info.height as i32);
+        }
+        let cams = cam_qry.iter()
+            .filter(|(_, cam_target)| cam_target.copied().unwrap_or_default().0 == *target)
+            .map(|(cam, _)| cam);
+        draw_window(cams, &model_qry, &lights, &render_objs, target_info, &time);
+    }
+
+    // Leave the primary window current for the runner
+    if let Some(window) = &window {
+        window.make_current(WindowRef::Primary);
+    }
+}
+
+fn draw_window<'a>(
+    cams: impl Iterator<Item = &'a Camera>,
+    model_qry: &Query<(&Model, &GlobalTransform, Option<&Material>)>,
+    lights: &Lights,
+    render_objs: &RenderObjs,
+    window_info: &WindowInfo,
+    time: &Time,
+) {
     unsafe {
         gl::Enable(gl::DEPTH_TEST);
         //gl::DepthFunc(gl::ALWAYS);
@@ -199,15 +285,27 @@ pub fn draw(
         gl::ClearColor(0.2, 0.3, 0.3, 1.0);
         //gl::ClearColor(0.0, 0.0, 0.0, 1.0);
         gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-        
-        let point_light_positions = [
-            Vec3::new(0.7, 0.2, 2.0),
-            Vec3::new(2.3, -3.3, -4.0),
-            Vec3::new(-4.0, 2.0, -12.0),
-            Vec3::new(0.0, 0.0, -3.0),
-        ];
-        
+    }
+
+    for cam in cams {
+        draw_camera(cam, model_qry, lights, render_objs, window_info, time);
+    }
+}
+
+fn draw_camera(
+    cam: &Camera,
+    model_qry: &Query<(&Model, &GlobalTransform, Option<&Material>)>,
+    lights: &Lights,
+    render_objs: &RenderObjs,
+    window_info: &WindowInfo,
+    time: &Time,
+) {
+    unsafe {
+
         /*
+        let point_light_positions: Vec<Vec3> = lights.point_lights.iter()
+            .map(|(_, transform)| transform.translation())
+            .collect();
         draw_point_lights(
             &point_light_positions,
             &render_objs,
@@ -219,75 +317,127 @@ pub fn draw(
         let shader = &render_objs.lit_shader;
         shader.activate();
         set_lit_shader_uniforms(
-            &render_objs,
+            render_objs,
             cam,
-            &window_info,
-            &time,
-            &point_light_positions
+            window_info,
+            time,
+            lights,
         );
-        render_objs.model.draw(shader);
+        for (model, transform, material) in model_qry {
+            set_model_uniforms(shader, cam, transform.matrix(), material);
+            model.draw(shader);
+        }
     }
 }
 
+/// The windows to draw into and their sizes
+#[derive(SystemParam)]
+pub struct Windows<'w, 's> {
+    window_info: Res<'w, WindowInfo>,
+    secondary_qry: Query<'w, 's, (Entity, &'static SecondaryWindow)>,
+    window: Option<NonSend<'w, Window>>,
+}
+
+/// Every light in the scene, as consumed by the lit shader
+#[derive(SystemParam)]
+pub struct Lights<'w, 's> {
+    dir_lights: Query<'w, 's, &'static DirectionalLight>,
+    point_lights: Query<'w, 's, (&'static PointLight, &'static GlobalTransform)>,
+    spot_lights: Query<'w, 's, &'static SpotLight>,
+}
+
 unsafe fn set_lit_shader_uniforms(
     render_objs: &RenderObjs,
     camera: &Camera,
     window_info: &WindowInfo,
     time: &Time,
-    point_light_positions: &[Vec3],
+    lights: &Lights,
 ) {
     let shader = &render_objs.lit_shader;
 
     // vertex shader uniforms
-    let model = Mat4::from_translation(Vec3::ZERO);
+    // the model and normal matrices are set per object by set_model_uniforms
     let view = camera.get_view_mat();
     let proj = camera.get_projection_mat(
         window_info.width as f32,
         window_info.height as f32,
     );
-    let normal_mat = {
-        let mat = (view * model)
-            .inverse()
-            .transpose();
-        Mat3::from_mat4(mat)
-    };
-    shader.set_mat4("model", model);
     shader.set_mat4("view", view);
     shader.set_mat4("proj", proj);
-    shader.set_mat3("normal_mat", normal_mat);
     
     // fragment shader uniforms
     // material textures are handled by the mesh's draw method
-    shader.set_float("material.shininess", 64.0);
-    
     shader.set_float("time", time.current);
 
     // lights
-    shader.set_vec3("dir_light.direction", -0.2, -1.0, -0.3);
-    shader.set_vec3("dir_light.ambient", 0.05, 0.05, 0.05);
-    shader.set_vec3("dir_light.diffuse", 0.4, 0.4, 0.4);
-    shader.set_vec3("dir_light.specular", 0.5, 0.5, 0.5);
+    // the shader has exactly one slot for directional and spot lights, so unused slots are set to black
+    let dir_light = lights.dir_lights.iter().next();
+    let (direction, ambient, diffuse, specular) = match dir_light {
+        Some(light) => (light.direction, light.ambient, light.diffuse, light.specular),
+        None => (Vec3::NEG_Y, Vec3::ZERO, Vec3::ZERO, Vec3::ZERO),
+    };
+    set_vec3(shader, "dir_light.direction", direction);
+    set_vec3(shader, "dir_light.ambient", ambient);
+    set_vec3(shader, "dir_light.diffuse", diffuse);
+    set_vec3(shader, "dir_light.specular", specular);
     
-    for (i, pos) in point_light_positions.iter().enumerate() {
-        shader.set_vec3(format!("point_lights[{i}].position").as_str(), pos.x, pos.y, pos.z);
-        shader.set_vec3(format!("point_lights[{i}].ambient").as_str(), 0.05, 0.05, 0.05);
-        shader.set_vec3(format!("point_lights[{i}].diffuse").as_str(), 0.8, 0.8, 0.8);
-        shader.set_vec3(format!("point_lights[{i}].specular").as_str(), 1.0, 1.0, 1.0);
-        shader.set_float(format!("point_lights[{i}].att_constant").as_str(), 1.0);
-        shader.set_float(format!("point_lights[{i}].att_linear").as_str(), 0.09);
-        shader.set_float(format!("point_lights[{i}].att_quadratic").as_str(), 0.032);
+    let mut point_lights = lights.point_lights.iter();
+    for i in 0..NR_POINT_LIGHTS {
+        let (position, ambient, diffuse, specular, att) = match point_lights.next() {
+            Some((light, transform)) => (
+                transform.translation(), light.ambient, light.diffuse, light.specular, light.attenuation
+            ),
+            None => (Vec3::ZERO, Vec3::ZERO, Vec3::ZERO, Vec3::ZERO, Attenuation::default()),
+        };
+        set_vec3(shader, format!("point_lights[{i}].position").as_str(), position);
+        set_vec3(shader, format!("point_lights[{i}].ambient").as_str(), ambient);
+        set_vec3(shader, format!("point_lights[{i}].diffuse").as_str(), diffuse);
+        set_vec3(shader, format!("point_lights[{i}].specular").as_str(), specular);
+        shader.set_float(format!("point_lights[{i}].att_constant").as_str(), att.constant);
+        shader.set_float(format!("point_lights[{i}].att_linear").as_str(), att.linear);
+        shader.set_float(format!("point_lights[{i}].att_quadratic").as_str(), att.quadratic);
     }
 
-    shader.set_vec3("spot_light.position", 0.0, 0.0, 0.0);
-    shader.set_vec3("spot_light.direction", 0.0, 0.0, -1.0);
-    shader.set_float("spot_light.cutoff_angle_cos", 12.5f32.to_radians().cos());
-    shader.set_float("spot_light.outer_cutoff_angle_cos", 15.0f32.to_radians().cos());
-    shader.set_vec3("spot_light.ambient", 0.0, 0.0, 0.0);
-    shader.set_vec3("spot_light.diffuse", 1.0, 1.0, 1.0);
-    shader.set_vec3("spot_light.specular", 1.0, 1.0, 1.0);
-    shader.set_float("spot_light.att_constant", 1.0);
-    shader.set_float("spot_light.att_linear", 0.09);
-    shader.set_float("spot_light.att_quadratic", 0.032);
+    let spot_light = lights.spot_lights.iter().next();
+    let unlit_spot_light = SpotLight {
+        position: Vec3::ZERO,
+        direction: Vec3::NEG_Z,
+        cutoff_angle: 0.0,
+        outer_cutoff_angle: 0.0,
+        ambient: Vec3::ZERO,
+        diffuse: Vec3::ZERO,
+        specular: Vec3::ZERO,
+        attenuation: Attenuation::default(),
+    };
+    let spot_light = spot_light.unwrap_or(&unlit_spot_light);
+    set_vec3(shader, "spot_light.position", spot_light.position);
+    set_vec3(shader, "spot_light.direction", spot_light.direction);
+    shader.set_float("spot_light.cutoff_angle_cos", spot_light.cutoff_angle.to_radians().cos());
+    shader.set_float("spot_light.outer_cutoff_angle_cos", spot_light.outer_cutoff_angle.to_radians().cos());
+    set_vec3(shader, "spot_light.ambient", spot_light.ambient);
+    set_vec3(shader, "spot_light.diffuse", spot_light.diffuse);
+    set_vec3(shader, "spot_light.specular", spot_light.specular);
+    shader.set_float("spot_light.att_constant", spot_light.attenuation.constant);
+    shader.set_float("spot_light.att_linear", spot_light.attenuation.linear);
+    shader.set_float("spot_light.att_quadratic", spot_light.attenuation.quadratic);
+}
+
+unsafe fn set_vec3(shader: &Shader, name: &str, v: Vec3) {
+    shader.set_vec3(name, v.x, v.y, v.z);
+}
+
+unsafe fn set_model_uniforms(shader: &Shader, camera: &Camera, model: Mat4, material: Option<&Material>) {
+    let normal_mat = {
+        let mat = (camera.get_view_mat() * model)
+            .inverse()
+            .transpose();
+        Mat3::from_mat4(mat)
+    };
+    shader.set_mat4("model", model);
+    shader.set_mat3("normal_mat", normal_mat);
+    
+    let shininess = material.map_or(Material::default().shininess, |m| m.shininess);
+    shader.set_float("material.shininess", shininess);
 }
 
 unsafe fn set_unlit_shader_uniforms(
@@ -1,26 +1,46 @@
-use std::{path::Path, ffi::{c_void, CStr}};
+use std::{fs, ffi::{c_void, CStr}};
 
-use image::DynamicImage;
+use image::{DynamicImage, ImageFormat};
 
-pub unsafe fn load_texture(filepath: &str) -> u32 {
+use crate::error::EngineError;
+
+pub unsafe fn load_texture(filepath: &str) -> Result<u32, EngineError> {
+    let img = decode_texture(filepath, &read_file(filepath)?)?;
+    Ok(upload_texture(&img, filepath))
+}
+
+pub fn read_file(filepath: &str) -> Result<Vec<u8>, EngineError> {
+    fs::read(filepath).map_err(|source| EngineError::Io { path: filepath.into(), source })
+}
+
+/// Decode the contents of an image file, flipped so it can be uploaded as is.
+/// The format comes from the extension of `filepath`, or is guessed if it has none.
+/// Doesn't touch GL, so it can run on any thread.
+pub fn decode_texture(filepath: &str, bytes: &[u8]) -> Result<DynamicImage, EngineError> {
+    let img = match ImageFormat::from_path(filepath) {
+        Ok(format) => image::load_from_memory_with_format(bytes, format),
+        Err(_) => image::load_from_memory(bytes),
+    };
+    let img = img.map_err(|source| EngineError::Texture { path: filepath.into(), source })?;
+    Ok(img.flipv()) // flip loaded texture on the y-axis
+}
+
+/// Create a GL texture from a decoded image. `filepath` is only used in messages.
+pub unsafe fn upload_texture(img: &DynamicImage, filepath: &str) -> u32 {
     let mut texture = 0;
     gl::GenTextures(1, &mut texture);
     gl::BindTexture(gl::TEXTURE_2D, texture);
-    
-    // load image
-    let img = image::open(Path::new(filepath))
-        .expect("failed to load texture");
+
     let format = match &img {
         DynamicImage::ImageLuma8(_) => gl::RED,
         DynamicImage::ImageLumaA8(_) => gl::RG,
         DynamicImage::ImageRgb8(_) => gl::RGB,
         DynamicImage::ImageRgba8(_) => gl::RGBA,
         other => {
-            println!("no format found for {:?}", other);
+            log::warn!("no format found for {:?} in {}, assuming RGB", other.color(), filepath);
             gl::RGB
         }
     };
-    let img = img.flipv(); // flip loaded texture on the y-axis
     let data = img.as_bytes();
     
     // create texture
@@ -67,6 +87,6 @@ pub fn get_gl_string(variant: gl::types::GLenum) -> Option<&'static CStr> {
 #[macro_export]
 macro_rules! offset_of {
     ($ty:ty, $field:ident) => {
-        &(*(std::ptr::null() as *const $ty)).$field as *const _ as usize
+        std::mem::offset_of!($ty, $field)
     }
 }
\ No newline at end of file
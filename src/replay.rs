@@ -0,0 +1,231 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+};
+
+use bevy_ecs::{
+    event::{Events, ManualEventReader},
+    prelude::EventReader,
+    system::{Res, ResMut, Resource},
+    world::World,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{Time, TimePlugin, advance_time_res},
+    ecs::{Plugin, EcsBuilder, Incomplete, AppExit, Last, run_update_schedules},
+    error::EngineError,
+    headless::run_startup,
+    input::{Input, InputEvent, InputPlugin, replay_input},
+};
+
+
+/// Records every InputEvent with the frame it was handled in and that frame's delta,
+/// so the session can be re-run with `replay_runner`.
+///
+/// The file is written as JSON lines, one frame per line, and flushed every frame
+/// so a recording survives a crash.
+pub struct InputRecorderPlugin {
+    pub path: String,
+}
+
+impl InputRecorderPlugin {
+    pub fn new(path: &str) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Plugin for InputRecorderPlugin {
+    fn build(&self, ecs_builder: EcsBuilder<Incomplete>) -> EcsBuilder<Incomplete> {
+        let file = match File::create(&self.path) {
+            Ok(file) => file,
+            Err(err) => {
+                log::error!("failed to create input recording {}, not recording: {err}", self.path);
+                return ecs_builder;
+            },
+        };
+        log::info!("recording input to {}", self.path);
+
+        ecs_builder
+            .insert_resource(InputRecorder {
+                path: self.path.clone(),
+                writer: BufWriter::new(file),
+            })
+            .add_system(record_input, Last)
+    }
+
+    fn dependencies(&self) -> Vec<Box<dyn Plugin>> {
+        vec![Box::new(TimePlugin), Box::new(InputPlugin)]
+    }
+}
+
+/// The inputs handled during one frame
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    /// `Time::frame_count` of the frame
+    pub frame: u64,
+    /// `Time::unscaled_delta` of the frame, i.e. before pausing and time scaling
+    pub delta: f32,
+    pub inputs: Vec<Input>,
+}
+
+#[derive(Resource)]
+pub struct InputRecorder {
+    path: String,
+    writer: BufWriter<File>,
+}
+
+impl InputRecorder {
+    fn write_frame(&mut self, frame: &RecordedFrame) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, frame)?;
+        writeln!(self.writer)?;
+        self.writer.flush()
+    }
+}
+
+pub fn record_input(
+    mut recorder: ResMut<InputRecorder>,
+    time: Res<Time>,
+    mut input_rdr: EventReader<InputEvent>,
+) {
+    let frame = RecordedFrame {
+        frame: time.frame_count,
+        delta: time.unscaled_delta,
+        inputs: input_rdr.iter().map(|evt| evt.0.clone()).collect(),
+    };
+
+    if let Err(err) = recorder.write_frame(&frame) {
+        log::error!("failed to write input recording {}: {err}", recorder.path);
+    }
+}
+
+/// Read a recording written by InputRecorderPlugin
+pub fn load_recording(path: &str) -> Result<Vec<RecordedFrame>, EngineError> {
+    let source = fs::read_to_string(path)
+        .map_err(|source| EngineError::Io { path: path.into(), source })?;
+    source.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line)
+            .map_err(|source| EngineError::Parse { path: path.into(), source }))
+        .collect()
+}
+
+/// Configures `replay_runner`. Must be inserted as a resource before building the Ecs.
+#[derive(Resource, Clone)]
+pub struct InputReplay {
+    /// Recording written by InputRecorderPlugin
+    pub path: String,
+}
+
+/// Headless runner that re-runs a recorded session: every frame, the recorded inputs are sent
+/// in place of winit events and Time advances by the recorded delta, so the World ends up
+/// in the same state as in the recorded session.
+/// Like `headless_runner`, the Render schedule is never run. Stops after the last recorded frame
+/// or when an AppExit event is sent.
+pub fn replay_runner(mut world: World) -> World {
+    let Some(replay) = world.get_resource::<InputReplay>().cloned() else {
+        log::error!("replay_runner requires the InputReplay resource");
+        return world;
+    };
+    let frames = match load_recording(&replay.path) {
+        Ok(frames) => frames,
+        Err(err) => {
+            log::error!("{err}");
+            return world;
+        },
+    };
+    log::info!("replaying {} frames from {}", frames.len(), replay.path);
+
+    let mut app_exit_reader = ManualEventReader::<AppExit>::default();
+    if !run_startup(&mut world) {
+        return world;
+    }
+
+    for frame in frames {
+        for input in frame.inputs {
+            replay_input(input, &mut world);
+        }
+        advance_time_res(frame.delta, &mut world);
+        run_update_schedules(&mut world);
+
+        let app_exit_events = world.resource::<Events<AppExit>>();
+        if app_exit_reader.iter(app_exit_events).last().is_some() {
+            break;
+        }
+    }
+
+    world
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use glam::Vec2;
+    use winit::event::VirtualKeyCode;
+
+    use super::*;
+    use crate::{
+        ecs::First,
+        headless::{HeadlessRunner, headless_runner},
+        render::camera::{Camera, CameraPlugin},
+        scene::ScenePlugin,
+    };
+
+    const SCENE: &str = "assets/scenes/backpack.json";
+
+    fn keys(keys: &[VirtualKeyCode]) -> Option<HashSet<VirtualKeyCode>> {
+        Some(keys.iter().copied().collect())
+    }
+
+    /// Stands in for winit, scripting the inputs of the recorded session
+    fn scripted_input(world: &mut World) {
+        let input = match world.resource::<Time>().frame_count {
+            2 => Input { keydowns: keys(&[VirtualKeyCode::W, VirtualKeyCode::Right]), ..Default::default() },
+            5 => Input { mouse_scroll_delta: 2.0, mouse_motion: Some(Vec2::new(30.0, -10.0)), ..Default::default() },
+            7 => Input { keyups: keys(&[VirtualKeyCode::Right]), ..Default::default() },
+            10 => Input { keyups: keys(&[VirtualKeyCode::W]), ..Default::default() },
+            _ => return,
+        };
+        replay_input(input, world);
+    }
+
+    fn session(ecs_builder: EcsBuilder<Incomplete>, runner: fn(World) -> World) -> World {
+        ecs_builder
+            .add_plugin(CameraPlugin)
+            .add_plugin(ScenePlugin::headless(SCENE))
+            .set_runner(runner)
+            .build()
+            .run()
+    }
+
+    fn camera(world: &mut World) -> Camera {
+        world.query::<&Camera>().single(world).clone()
+    }
+
+    #[test]
+    fn replay_ends_in_the_recorded_state() {
+        let path = std::env::temp_dir().join(format!("engine_replay_test_{}.jsonl", std::process::id())).to_string_lossy().into_owned();
+
+        let mut recorded = session(EcsBuilder::new()
+            .add_plugin(InputRecorderPlugin::new(&path))
+            .insert_resource(HeadlessRunner { frames: 12, delta: 0.05 })
+            .add_system(scripted_input, First), headless_runner);
+        let mut replayed = session(EcsBuilder::new()
+            .insert_resource(InputReplay { path: path.clone() }), replay_runner);
+        let _ = fs::remove_file(&path);
+
+        let (recorded_camera, replayed_camera) = (camera(&mut recorded), camera(&mut replayed));
+        // The scene's camera moved, instead of a default one being spawned
+        assert_ne!(recorded_camera.position, glam::Vec3::new(0.0, 0.0, 3.0));
+        assert_eq!(replayed_camera.position, recorded_camera.position);
+        assert_eq!(replayed_camera.yaw, recorded_camera.yaw);
+        assert_eq!(replayed_camera.pitch, recorded_camera.pitch);
+        assert_eq!(replayed_camera.zoom, recorded_camera.zoom);
+
+        let (recorded_time, replayed_time) = (recorded.resource::<Time>(), replayed.resource::<Time>());
+        assert_eq!(replayed_time.frame_count, 12);
+        assert_eq!(replayed_time.frame_count, recorded_time.frame_count);
+        assert_eq!(replayed_time.current, recorded_time.current);
+    }
+}
@@ -0,0 +1,149 @@
+use std::time::Instant;
+
+use bevy_ecs::{prelude::Events, world::World, event::ManualEventReader};
+use winit::event::{Event, WindowEvent};
+
+use crate::{
+    common::update_time_res,
+    ecs::{StartupSingleThreaded, Startup, Render, AppExit, RedrawScheduler, run_update_schedules},
+    error::report_startup_errors,
+    input::{process_input_event, process_device_event},
+    window::{
+        Window, WindowInfo, WindowRef, GraphicsSettings, CursorGrab, sync_secondary_windows,
+        WindowCloseRequested, WindowResized, WindowScaleFactorChanged, WindowFocused, WindowMoved,
+        CursorEntered, CursorLeft, FileDropped, WindowThemeChanged,
+    },
+};
+
+
+/// Default runner: opens a window with the WindowInfo and GraphicsSettings resources (if present),
+/// runs the startup schedules once the GL context is current, then runs the update and Render schedules
+/// every frame until an AppExit event is sent. Frames run back to back or on demand
+/// depending on the UpdateMode resource. Never returns.
+pub fn winit_runner(mut world: World) -> World {
+    let window_info = world.get_resource::<WindowInfo>().cloned().unwrap_or_default();
+    let graphics = world.get_resource::<GraphicsSettings>().cloned().unwrap_or_default();
+    let (window, event_loop) = Window::new(&window_info, &graphics);
+    // Systems reach the window through this resource, e.g. to apply changes to WindowInfo
+    world.insert_non_send_resource(window);
+
+    let mut renderer_initialized = false;
+    let start_time = Instant::now();
+    let mut app_exit_reader = ManualEventReader::<AppExit>::default();
+    let mut redraw_scheduler = RedrawScheduler::default();
+    // Size sent for the last ScaleFactorChanged event, so the Resized event that may follow isn't sent twice
+    let mut scale_factor_resize = None;
+
+    event_loop.run(move |event, window_target, control_flow| {
+        match event {
+            Event::Resumed => {
+                // Make the window's context current and initialize some other things in Window.
+                // After a suspension this recreates the surfaces, but startup only runs once.
+                let window_info = world.get_resource::<WindowInfo>().cloned().unwrap_or_default();
+                let graphics = world.get_resource::<GraphicsSettings>().cloned().unwrap_or_default();
+                let mut window = world.non_send_resource_mut::<Window>();
+                window.resume(window_target, &window_info, &graphics);
+                let capabilities = window.capabilities().clone();
+                world.insert_resource(capabilities);
+                if renderer_initialized { return; }
+                
+                // Add window info as a resource
+                world.insert_resource(window_info);
+
+                // Run startup schedules
+                world.run_schedule(StartupSingleThreaded); // Renderer should be initialized here
+                world.run_schedule(Startup); // App logic should be initialized here
+                
+                if !report_startup_errors(&world) {
+                    control_flow.set_exit();
+                    return;
+                }
+                
+                renderer_initialized = true;
+            },
+            Event::Suspended => world.non_send_resource_mut::<Window>().suspend(),
+            Event::WindowEvent { window_id, event } => {
+                redraw_scheduler.wake();
+
+                // Events of a secondary window that was just closed are dropped
+                let Some(window) = world.non_send_resource::<Window>().window_ref(window_id) else { return };
+                // InputStates and InputEvents only follow the primary window
+                if window == WindowRef::Primary {
+                    process_input_event(&event, &mut world);
+                }
+
+                // Publish the window events for systems to react to
+                match event {
+                    WindowEvent::Resized(size) if size.width != 0 && size.height != 0 => {
+                        let already_sent = scale_factor_resize.take() == Some((window, size));
+                        if !already_sent {
+                            world.send_event(WindowResized { window, width: size.width, height: size.height });
+                        }
+                    },
+                    WindowEvent::ScaleFactorChanged { scale_factor, new_inner_size } => {
+                        world.send_event(WindowScaleFactorChanged { window, scale_factor });
+                        // Not every platform follows up with a Resized event
+                        if new_inner_size.width != 0 && new_inner_size.height != 0 {
+                            scale_factor_resize = Some((window, *new_inner_size));
+                            world.send_event(WindowResized {
+                                window,
+                                width: new_inner_size.width,
+                                height: new_inner_size.height,
+                            });
+                        }
+                    },
+                    WindowEvent::Focused(focused) => world.send_event(WindowFocused { window, focused }),
+                    WindowEvent::Moved(position) => world.send_event(WindowMoved { window, x: position.x, y: position.y }),
+                    WindowEvent::CursorEntered { .. } => world.send_event(CursorEntered { window }),
+                    WindowEvent::CursorLeft { .. } => world.send_event(CursorLeft { window }),
+                    WindowEvent::DroppedFile(path) => world.send_event(FileDropped { window, path }),
+                    WindowEvent::ThemeChanged(theme) => world.send_event(WindowThemeChanged { window, theme }),
+                    WindowEvent::CloseRequested => world.send_event(WindowCloseRequested { window }),
+                    _ => (),
+                }
+            },
+            // Device events arrive whatever window has focus, so they're only used while the cursor is grabbed
+            Event::DeviceEvent { event, .. }
+                if world.non_send_resource::<Window>().cursor_grab() != CursorGrab::None =>
+            {
+                redraw_scheduler.wake();
+                process_device_event(&event, &mut world);
+            },
+            Event::MainEventsCleared => {
+                // A Resized event following a ScaleFactorChanged one arrives in the same batch
+                scale_factor_resize = None;
+                if !renderer_initialized { return; }
+
+                let now = start_time.elapsed();
+                if redraw_scheduler.should_run(&world, now) {
+                    update_time_res(start_time, &mut world);
+
+                    run_update_schedules(&mut world);
+                    sync_secondary_windows(&mut world, window_target);
+                    {
+                        #[cfg(feature = "diagnostics")]
+                        let _timings = crate::diagnostics::collect_timings(&world);
+                        world.run_schedule(Render);
+                    }
+
+                    world.non_send_resource::<Window>().swap_buffers();
+                    redraw_scheduler.frame_finished(&world, now);
+
+                    let app_exit_events = world.resource::<Events<AppExit>>();
+                    if app_exit_reader.iter(app_exit_events).last().is_some() {
+                        control_flow.set_exit();
+                        return;
+                    }
+                }
+
+                // Sleep until the next frame is due, or until an event wakes the scheduler up
+                match redraw_scheduler.next_frame(&world) {
+                    Some(next_frame) if next_frame <= start_time.elapsed() => control_flow.set_poll(),
+                    Some(next_frame) => control_flow.set_wait_until(start_time + next_frame),
+                    None => control_flow.set_wait(),
+                }
+            },
+            _ => (),
+        }
+    });
+}
\ No newline at end of file
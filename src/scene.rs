@@ -0,0 +1,447 @@
+use std::{any::TypeId, collections::{BTreeMap, HashMap, HashSet}, fs};
+
+use bevy_ecs::{
+    prelude::{Component, Entity, With},
+    system::{Command, Resource},
+    world::{EntityMut, EntityRef, Mut, World},
+};
+use serde::{de::{DeserializeOwned, Error as _}, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    common::Name,
+    ecs::{Plugin, EcsBuilder, Incomplete, StartupSingleThreaded},
+    error::{EngineError, StartupAssetLoads, StartupErrors},
+    render::{
+        camera::{Camera, CameraMovement},
+        light::{DirectionalLight, PointLight, SpotLight},
+        material::Material,
+        model::{Model, LoadingModel},
+        TextureType,
+    },
+    tasks::{ComputeTasks, IoTasks, TaskPoolPlugin},
+    transform::{Children, GlobalTransform, Parent, SetParent, Transform, TransformBundle, TransformPlugin},
+};
+
+
+/// Spawns the entities of a scene file into the World at startup,
+/// and registers the engine's components with the SceneRegistry
+pub struct ScenePlugin {
+    pub path: String,
+    /// Load the scene's models in the background for the renderer to upload.
+    /// Without a renderer (e.g. when replaying headlessly), entities only keep their model's path.
+    pub load_models: bool,
+}
+
+impl ScenePlugin {
+    pub fn new(path: &str) -> Self {
+        Self { path: path.into(), load_models: true }
+    }
+
+    /// Spawn the scene without loading its models, for runs without a GL context
+    pub fn headless(path: &str) -> Self {
+        Self { path: path.into(), load_models: false }
+    }
+}
+
+impl Plugin for ScenePlugin {
+    fn build(&self, ecs_builder: EcsBuilder<Incomplete>) -> EcsBuilder<Incomplete> {
+        let mut registry = SceneRegistry::default();
+        registry
+            .register::<Name>("name")
+            .register_with::<Transform>("transform", serialize_component::<Transform>, deserialize_transform)
+            .register_with::<Model>("model", serialize_model, deserialize_model)
+            .register::<Material>("material")
+            .register::<DirectionalLight>("directional_light")
+            .register::<PointLight>("point_light")
+            .register::<SpotLight>("spot_light")
+            .register::<Camera>("camera")
+            .register::<CameraMovement>("camera_movement")
+            .register_with::<Parent>(PARENT, serialize_parent, deserialize_parent)
+            // computed from Transform by propagate_transforms
+            .ignore::<GlobalTransform>()
+            // rebuilt from the children's "parent" when loading
+            .ignore::<Children>()
+            // saved through the "model" hooks while still loading or not loaded at all
+            .ignore::<LoadingModel>()
+            .ignore::<PendingModel>();
+
+        ecs_builder
+            .insert_resource(registry)
+            .insert_resource(SceneSettings { load_models: self.load_models })
+            .insert_resource(StartupScene(self.path.clone()))
+            .add_system(load_startup_scene, StartupSingleThreaded)
+    }
+
+    fn dependencies(&self) -> Vec<Box<dyn Plugin>> {
+        vec![Box::new(TransformPlugin), Box::new(TaskPoolPlugin::default())]
+    }
+}
+
+#[derive(Resource)]
+struct StartupScene(String);
+
+/// How `spawn_scene` treats the scene's assets, see `ScenePlugin`
+#[derive(Resource)]
+pub struct SceneSettings {
+    pub load_models: bool,
+}
+
+/// A scene file is a JSON object with a list of entities.
+/// Each entity is an object mapping component names to their values, e.g.
+/// `{ "name": "backpack", "transform": { "translation": [0, 1, 0] }, "model": { "path": "a.obj" } }`
+#[derive(Serialize, Deserialize)]
+pub struct Scene {
+    pub entities: Vec<SceneEntity>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SceneEntity {
+    #[serde(flatten)]
+    pub components: BTreeMap<String, Value>,
+}
+
+type SerializeFn = fn(&EntityRef) -> Option<serde_json::Result<Value>>;
+type DeserializeFn = fn(&mut EntityMut, Value) -> Result<(), EngineError>;
+
+/// How a component type is written to and read from a scene file
+pub struct ComponentRegistration {
+    pub name: &'static str,
+    type_id: TypeId,
+    serialize: SerializeFn,
+    deserialize: DeserializeFn,
+}
+
+/// Component types that can be saved to and loaded from scene files, keyed by their name in the file
+#[derive(Resource, Default)]
+pub struct SceneRegistry {
+    registrations: Vec<ComponentRegistration>,
+    // Components that are known but deliberately not saved, e.g. because they are derived from others
+    ignored: HashSet<TypeId>,
+}
+
+impl SceneRegistry {
+    /// Register a component that is stored in the file exactly as it serializes
+    pub fn register<C: Component + Serialize + DeserializeOwned>(&mut self, name: &'static str) -> &mut Self {
+        self.register_with::<C>(name, serialize_component::<C>, deserialize_component::<C>)
+    }
+
+    /// Register a component with custom hooks, e.g. for components holding GPU resources
+    pub fn register_with<C: Component>(&mut self,
+        name: &'static str,
+        serialize: SerializeFn,
+        deserialize: DeserializeFn,
+    ) -> &mut Self {
+        if self.get(name).is_some() {
+            panic!("scene component \"{name}\" is already registered");
+        }
+        self.registrations.push(ComponentRegistration {
+            name,
+            type_id: TypeId::of::<C>(),
+            serialize,
+            deserialize,
+        });
+        self
+    }
+
+    /// Skip a component type when saving without reporting it as unknown
+    pub fn ignore<C: Component>(&mut self) -> &mut Self {
+        self.ignored.insert(TypeId::of::<C>());
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ComponentRegistration> {
+        self.registrations.iter().find(|r| r.name == name)
+    }
+
+    fn is_known(&self, type_id: TypeId) -> bool {
+        self.ignored.contains(&type_id) || self.registrations.iter().any(|r| r.type_id == type_id)
+    }
+}
+
+pub fn serialize_component<C: Component + Serialize>(entity: &EntityRef) -> Option<serde_json::Result<Value>> {
+    entity.get::<C>().map(to_value)
+}
+
+/// Like `serde_json::to_value`, but keeps f32s short (0.05 instead of 0.05000000074505806)
+/// by letting serde_json format them as f32 before they are widened to f64
+pub fn to_value<T: Serialize>(value: &T) -> serde_json::Result<Value> {
+    serde_json::from_str(&serde_json::to_string(value)?)
+}
+
+pub fn deserialize_component<C: Component + DeserializeOwned>(entity: &mut EntityMut, value: Value) -> Result<(), EngineError> {
+    entity.insert(serde_json::from_value::<C>(value)?);
+    Ok(())
+}
+
+fn deserialize_transform(entity: &mut EntityMut, value: Value) -> Result<(), EngineError> {
+    let transform = serde_json::from_value::<Transform>(value)?;
+    entity.insert(TransformBundle::from_transform(transform));
+    Ok(())
+}
+
+/// Scene form of a Model, which is loaded from an OBJ file
+#[derive(Serialize, Deserialize)]
+struct ModelDesc {
+    path: String,
+}
+
+fn serialize_model(entity: &EntityRef) -> Option<serde_json::Result<Value>> {
+    let path = match (entity.get::<Model>(), entity.get::<LoadingModel>(), entity.get::<PendingModel>()) {
+        (Some(model), _, _) => model.filepath(),
+        (None, Some(loading), _) => loading.filepath(),
+        (None, None, Some(PendingModel(path))) => path,
+        (None, None, None) => return None,
+    };
+    Some(to_value(&ModelDesc { path: path.into() }))
+}
+
+fn deserialize_model(entity: &mut EntityMut, value: Value) -> Result<(), EngineError> {
+    let desc = serde_json::from_value::<ModelDesc>(value)?;
+    entity.insert(PendingModel(desc.path));
+    Ok(())
+}
+
+/// Name of the Parent component in scene files. The parent is stored as its index in the file's entity list,
+/// as entity ids aren't stable between runs.
+const PARENT: &str = "parent";
+
+/// Writes the parent's entity id, which `serialize_scene` replaces by its index in the file
+fn serialize_parent(entity: &EntityRef) -> Option<serde_json::Result<Value>> {
+    entity.get::<Parent>().map(|parent| to_value(&parent.get().to_bits()))
+}
+
+fn deserialize_parent(entity: &mut EntityMut, value: Value) -> Result<(), EngineError> {
+    entity.insert(PendingParent(serde_json::from_value(value)?));
+    Ok(())
+}
+
+/// Index of the parent in the scene file, resolved once every entity of the scene has been spawned
+#[derive(Component)]
+struct PendingParent(usize);
+
+/// Model to start loading once every component of the entity has been deserialized.
+/// Stays on the entity if `SceneSettings::load_models` is off.
+#[derive(Component)]
+struct PendingModel(String);
+
+/// A component that was on a saved entity but couldn't be written to the scene file
+#[derive(Debug)]
+pub struct UnknownComponent {
+    pub entity: Entity,
+    pub component: String,
+}
+
+/// Outcome of `save_scene`
+#[derive(Debug, Default)]
+pub struct SaveReport {
+    pub saved_entities: usize,
+    /// Components without a SceneRegistry registration, which were left out of the file
+    pub unknown_components: Vec<UnknownComponent>,
+}
+
+fn load_startup_scene(world: &mut World) {
+    let path = world.remove_resource::<StartupScene>().unwrap().0;
+    if let Err(err) = load_scene(world, &path) {
+        world.resource_mut::<StartupErrors>().0.push(err);
+    }
+
+    let pending: HashSet<Entity> = world.query_filtered::<Entity, With<LoadingModel>>().iter(world).collect();
+    if !pending.is_empty() {
+        world.insert_resource(StartupAssetLoads { pending, errors: Vec::new() });
+    }
+}
+
+/// Spawn every entity of the scene file at `path`, returning the spawned entities in file order.
+/// Models are loaded in the background on the task pools and appear once uploaded by the renderer,
+/// so their errors are reported then (see `AssetErrors` and `StartupAssetLoads`) instead of being returned here.
+/// They aren't loaded at all if `SceneSettings::load_models` is off.
+///
+/// Components that fail to load are skipped, and all of their errors are returned together
+/// once the rest of the scene has been spawned.
+pub fn load_scene(world: &mut World, path: &str) -> Result<Vec<Entity>, EngineError> {
+    let src = fs::read_to_string(path)
+        .map_err(|source| EngineError::Io { path: path.into(), source })?;
+    let scene: Scene = serde_json::from_str(&src)
+        .map_err(|source| EngineError::Parse { path: path.into(), source })?;
+
+    let (entities, errors) = spawn_scene(world, scene);
+    if errors.is_empty() {
+        Ok(entities)
+    } else {
+        Err(EngineError::Scene { path: path.into(), errors })
+    }
+}
+
+/// Spawn the entities of a scene, returning them in order along with the errors of every component that failed
+pub fn spawn_scene(world: &mut World, scene: Scene) -> (Vec<Entity>, Vec<EngineError>) {
+    let pools = world.resource::<SceneSettings>().load_models
+        .then(|| (*world.resource::<IoTasks>(), *world.resource::<ComputeTasks>()));
+    world.resource_scope(|world, registry: Mut<SceneRegistry>| {
+        let mut entities = Vec::with_capacity(scene.entities.len());
+        let mut errors = Vec::new();
+        for scene_entity in scene.entities {
+            let mut entity = world.spawn_empty();
+            entities.push(entity.id());
+            for (name, value) in scene_entity.components {
+                let Some(registration) = registry.get(&name) else {
+                    errors.push(EngineError::UnknownComponent { name });
+                    continue;
+                };
+                if let Err(err) = (registration.deserialize)(&mut entity, value) {
+                    errors.push(EngineError::InvalidComponent { name, source: Box::new(err) });
+                }
+            }
+            if let Some((io_tasks, compute_tasks)) = pools {
+                start_model_load(&mut entity, io_tasks, &compute_tasks);
+            }
+        }
+
+        for &child in &entities {
+            let Some(PendingParent(index)) = world.entity_mut(child).take::<PendingParent>() else { continue };
+            match entities.get(index) {
+                Some(&parent) => SetParent { child, parent }.write(world),
+                None => errors.push(EngineError::InvalidComponent {
+                    name: PARENT.into(),
+                    source: Box::new(EngineError::Json(serde_json::Error::custom(
+                        format!("there is no entity {index} in the scene")
+                    ))),
+                }),
+            }
+        }
+        (entities, errors)
+    })
+}
+
+/// Write every entity with at least one registered component to the scene file at `path`
+pub fn save_scene(world: &World, path: &str) -> Result<SaveReport, EngineError> {
+    let (scene, report) = serialize_scene(world)?;
+    let json = serde_json::to_string_pretty(&scene)?;
+    fs::write(path, json).map_err(|source| EngineError::Io { path: path.into(), source })?;
+    Ok(report)
+}
+
+pub fn serialize_scene(world: &World) -> Result<(Scene, SaveReport), EngineError> {
+    let registry = world.resource::<SceneRegistry>();
+    let mut report = SaveReport::default();
+
+    let mut entities: Vec<Entity> = world.iter_entities().map(|e| e.id()).collect();
+    entities.sort();
+
+    let mut scene = Scene { entities: Vec::new() };
+    let mut saved = Vec::new();
+    for entity in entities {
+        let entity_ref = world.entity(entity);
+
+        let mut components = BTreeMap::new();
+        for registration in &registry.registrations {
+            if let Some(value) = (registration.serialize)(&entity_ref) {
+                let value = value.map_err(|err| EngineError::InvalidComponent {
+                    name: registration.name.into(),
+                    source: Box::new(err.into()),
+                })?;
+                components.insert(registration.name.to_string(), value);
+            }
+        }
+
+        let mut unknown = Vec::new();
+        for component_id in entity_ref.archetype().components() {
+            let info = world.components().get_info(component_id).unwrap();
+            if !info.type_id().is_some_and(|id| registry.is_known(id)) {
+                unknown.push(UnknownComponent { entity, component: info.name().into() });
+            }
+        }
+
+        // Entities made up only of unregistered components (e.g. engine internals) aren't saved,
+        // but their components are still reported
+        report.unknown_components.extend(unknown);
+        if !components.is_empty() {
+            report.saved_entities += 1;
+            scene.entities.push(SceneEntity { components });
+            saved.push(entity);
+        }
+    }
+
+    // Replace the parents' entity ids by their index in the file.
+    // Parents without any saved component aren't in the file, so their children are saved as roots.
+    let indices: HashMap<u64, usize> = saved.iter().enumerate().map(|(i, entity)| (entity.to_bits(), i)).collect();
+    for (scene_entity, entity) in scene.entities.iter_mut().zip(&saved) {
+        let Some(parent) = scene_entity.components.get(PARENT) else { continue };
+        match parent.as_u64().and_then(|bits| indices.get(&bits)) {
+            Some(index) => { scene_entity.components.insert(PARENT.into(), (*index).into()); },
+            None => {
+                log::warn!("the parent of {entity:?} has no saved component, saving {entity:?} as a root");
+                scene_entity.components.remove(PARENT);
+            },
+        }
+    }
+
+    Ok((scene, report))
+}
+
+/// Start loading the entity's model with its Material's texture overrides, now that both are on the entity
+fn start_model_load(entity: &mut EntityMut, io_tasks: IoTasks, compute_tasks: &ComputeTasks) {
+    let Some(PendingModel(path)) = entity.take::<PendingModel>() else { return };
+    let mut overrides = Vec::new();
+    if let Some(material) = entity.get::<Material>() {
+        if let Some(diffuse) = &material.diffuse {
+            overrides.push((diffuse.clone(), TextureType::Diffuse));
+        }
+        if let Some(specular) = &material.specular {
+            overrides.push((specular.clone(), TextureType::Specular));
+        }
+    }
+    entity.insert(LoadingModel::spawn(io_tasks, compute_tasks, &path, overrides));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene_world() -> World {
+        // The startup scene is never loaded, as this runner doesn't run the startup schedules
+        EcsBuilder::new()
+            .add_plugin(ScenePlugin::new("unused.json"))
+            .set_runner(|world| world)
+            .build()
+            .run()
+    }
+
+    #[test]
+    fn hierarchy_survives_save_and_load() {
+        let mut world = scene_world();
+        // Offset the ids so they differ from the loaded ones
+        world.spawn_empty();
+        let root = world.spawn((Name("root".into()), TransformBundle::default())).id();
+        let child = world.spawn((Name("child".into()), TransformBundle::default())).id();
+        let grandchild = world.spawn((Name("grandchild".into()), TransformBundle::default())).id();
+        SetParent { child, parent: root }.write(&mut world);
+        SetParent { child: grandchild, parent: child }.write(&mut world);
+
+        let (scene, report) = serialize_scene(&world).unwrap();
+        assert_eq!(report.saved_entities, 3);
+
+        let mut loaded = scene_world();
+        let (entities, errors) = spawn_scene(&mut loaded, scene);
+        assert!(errors.is_empty(), "{errors:?}");
+
+        let named = |name: &str| *entities.iter()
+            .find(|entity| loaded.get::<Name>(**entity).unwrap().0 == name)
+            .unwrap();
+        let (root, child, grandchild) = (named("root"), named("child"), named("grandchild"));
+        assert!(loaded.get::<Parent>(root).is_none());
+        assert_eq!(loaded.get::<Parent>(child).unwrap().get(), root);
+        assert_eq!(loaded.get::<Parent>(grandchild).unwrap().get(), child);
+        assert_eq!(loaded.get::<Children>(root).unwrap().iter().copied().collect::<Vec<_>>(), vec![child]);
+    }
+
+    #[test]
+    fn parent_out_of_the_scene_is_reported() {
+        let mut world = scene_world();
+        let scene: Scene = serde_json::from_str(r#"{ "entities": [{ "name": "orphan", "parent": 3 }] }"#).unwrap();
+        let (entities, errors) = spawn_scene(&mut world, scene);
+
+        assert_eq!(errors.len(), 1);
+        assert!(world.get::<Parent>(entities[0]).is_none());
+    }
+}
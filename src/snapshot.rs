@@ -0,0 +1,335 @@
+use std::{any::{Any, TypeId}, collections::HashSet};
+
+use bevy_ecs::{
+    prelude::{Component, Entity, EventReader, With},
+    system::{Res, ResMut, Resource},
+    world::{Mut, World},
+};
+use winit::event::VirtualKeyCode;
+
+use crate::{
+    common::{Name, Time, FixedTime, Timer, Stopwatch, TimePlugin, RingBuffer},
+    ecs::{Plugin, EcsBuilder, Incomplete, Update, Last},
+    input::{InputEvent, InputPlugin, KeyBindings},
+    render::{
+        camera::{Camera, CameraMovement},
+        light::{DirectionalLight, PointLight, SpotLight},
+        material::Material,
+    },
+    transform::{Children, GlobalTransform, Parent, Transform},
+};
+
+
+/// Keeps a ring buffer of snapshots of the registered components and resources,
+/// taken every `interval` frames, and rewinds the World to them on request.
+///
+/// Pressing the key bound to the "rewind" action (F9 by default) restores the latest snapshot;
+/// pressing it again before the next snapshot is taken goes back one more.
+/// Entities spawned after the snapshot are despawned, whatever their components.
+/// Time is restored except for `frame_count` and `unscaled_current`, which keep counting up.
+pub struct SnapshotPlugin {
+    /// Number of frames between two snapshots
+    pub interval: u32,
+    /// Number of snapshots kept, the oldest being dropped first
+    pub capacity: usize,
+}
+
+impl Default for SnapshotPlugin {
+    fn default() -> Self {
+        Self {
+            interval: 60,
+            capacity: 10,
+        }
+    }
+}
+
+impl Plugin for SnapshotPlugin {
+    fn build(&self, ecs_builder: EcsBuilder<Incomplete>) -> EcsBuilder<Incomplete> {
+        let mut registry = SnapshotRegistry::default();
+        registry
+            .register_component::<Name>()
+            .register_component::<Transform>()
+            .register_component::<GlobalTransform>()
+            .register_component::<Parent>()
+            .register_component::<Children>()
+            .register_component::<Camera>()
+            .register_component::<CameraMovement>()
+            .register_component::<DirectionalLight>()
+            .register_component::<PointLight>()
+            .register_component::<SpotLight>()
+            .register_component::<Material>()
+            .register_component::<Timer>()
+            .register_component::<Stopwatch>()
+            .register::<Time>(capture_resource::<Time>, restore_time)
+            .register_resource::<FixedTime>();
+
+        ecs_builder
+            .add_action("rewind", VirtualKeyCode::F9)
+            .insert_resource(registry)
+            .insert_resource(Snapshots::new(self.interval, self.capacity))
+            .add_system(rewind_on_key, Update)
+            .add_system(update_snapshots, Last)
+    }
+
+    fn dependencies(&self) -> Vec<Box<dyn Plugin>> {
+        vec![Box::new(TimePlugin), Box::new(InputPlugin)]
+    }
+}
+
+type CaptureFn = fn(&mut World) -> Option<Box<dyn Any + Send + Sync>>;
+type RestoreFn = fn(&mut World, &(dyn Any + Send + Sync));
+
+struct SnapshotRegistration {
+    type_id: TypeId,
+    capture: CaptureFn,
+    restore: RestoreFn,
+}
+
+/// Component and resource types captured by snapshots.
+/// Anything else (e.g. Models, which hold GPU resources) is left as is when rewinding.
+#[derive(Resource, Default)]
+pub struct SnapshotRegistry {
+    registrations: Vec<SnapshotRegistration>,
+}
+
+impl SnapshotRegistry {
+    pub fn register_component<C: Component + Clone>(&mut self) -> &mut Self {
+        self.register::<C>(capture_component::<C>, restore_component::<C>)
+    }
+
+    pub fn register_resource<R: Resource + Clone>(&mut self) -> &mut Self {
+        self.register::<R>(capture_resource::<R>, restore_resource::<R>)
+    }
+
+    fn register<T: 'static>(&mut self, capture: CaptureFn, restore: RestoreFn) -> &mut Self {
+        let type_id = TypeId::of::<T>();
+        if self.registrations.iter().any(|r| r.type_id == type_id) {
+            panic!("{} is already registered for snapshots", std::any::type_name::<T>());
+        }
+        self.registrations.push(SnapshotRegistration { type_id, capture, restore });
+        self
+    }
+}
+
+fn capture_component<C: Component + Clone>(world: &mut World) -> Option<Box<dyn Any + Send + Sync>> {
+    let components: Vec<(Entity, C)> = world.query::<(Entity, &C)>().iter(world)
+        .map(|(entity, component)| (entity, component.clone()))
+        .collect();
+    Some(Box::new(components))
+}
+
+/// Replace every C in the World by the captured ones.
+/// Entities despawned since the capture are spawned again with their old id if it's still free.
+fn restore_component<C: Component + Clone>(world: &mut World, data: &(dyn Any + Send + Sync)) {
+    let components = data.downcast_ref::<Vec<(Entity, C)>>().unwrap();
+
+    let current: Vec<Entity> = world.query_filtered::<Entity, With<C>>().iter(world).collect();
+    for entity in current {
+        world.entity_mut(entity).remove::<C>();
+    }
+    for (entity, component) in components {
+        match world.get_or_spawn(*entity) {
+            Some(mut entity_mut) => { entity_mut.insert(component.clone()); },
+            None => log::warn!(
+                "can't restore {} on {entity:?}, its id has been reused", std::any::type_name::<C>()
+            ),
+        }
+    }
+}
+
+fn capture_resource<R: Resource + Clone>(world: &mut World) -> Option<Box<dyn Any + Send + Sync>> {
+    world.get_resource::<R>().map(|resource| Box::new(resource.clone()) as Box<dyn Any + Send + Sync>)
+}
+
+fn restore_resource<R: Resource + Clone>(world: &mut World, data: &(dyn Any + Send + Sync)) {
+    world.insert_resource(data.downcast_ref::<R>().unwrap().clone());
+}
+
+/// Restore the game clock, but not the frame counter nor the wall-clock time, which only go forward
+fn restore_time(world: &mut World, data: &(dyn Any + Send + Sync)) {
+    let snapshot = data.downcast_ref::<Time>().unwrap();
+    let mut time = world.resource_mut::<Time>();
+    *time = Time {
+        frame_count: time.frame_count,
+        unscaled_current: time.unscaled_current,
+        ..snapshot.clone()
+    };
+}
+
+/// Copy of the registered components and resources at the end of a frame
+pub struct Snapshot {
+    /// `Time::frame_count` when the snapshot was taken
+    pub frame: u64,
+    /// Every entity that existed, so the ones spawned later can be despawned
+    entities: HashSet<Entity>,
+    data: Vec<(TypeId, Box<dyn Any + Send + Sync>)>,
+}
+
+/// Ring buffer of snapshots, oldest first
+#[derive(Resource)]
+pub struct Snapshots {
+    snapshots: RingBuffer<Snapshot>,
+    interval: u32,
+    frames_since_snapshot: u32,
+    requested_rewind: Option<usize>,
+}
+
+impl Snapshots {
+    pub fn new(interval: u32, capacity: usize) -> Self {
+        assert!(interval > 0, "snapshot interval must be positive");
+        Self {
+            snapshots: RingBuffer::new(capacity),
+            interval,
+            frames_since_snapshot: 0,
+            requested_rewind: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Frames at which the stored snapshots were taken, oldest first
+    pub fn frames(&self) -> impl Iterator<Item = u64> + '_ {
+        self.snapshots.iter().map(|snapshot| snapshot.frame)
+    }
+
+    /// Rewind at the end of the current frame, see `rewind`
+    pub fn request_rewind(&mut self, steps: usize) {
+        self.requested_rewind = Some(steps);
+    }
+}
+
+/// Capture the registered components and resources now, dropping the oldest snapshot if the buffer is full
+pub fn take_snapshot(world: &mut World) {
+    let frame = world.get_resource::<Time>().map_or(0, |time| time.frame_count);
+    let entities = world.iter_entities().map(|entity| entity.id()).collect();
+    let data = world.resource_scope(|world, registry: Mut<SnapshotRegistry>| {
+        registry.registrations.iter()
+            .filter_map(|registration| Some((registration.type_id, (registration.capture)(world)?)))
+            .collect()
+    });
+
+    let mut snapshots = world.resource_mut::<Snapshots>();
+    snapshots.snapshots.push(Snapshot { frame, entities, data });
+    snapshots.frames_since_snapshot = 0;
+}
+
+/// Restore the `steps`-th most recent snapshot (1 being the latest) and resume from it.
+/// Entities spawned since are despawned.
+/// That snapshot and every newer one are dropped, so rewinding again goes further back.
+/// Returns false if there are fewer than `steps` snapshots.
+pub fn rewind(world: &mut World, steps: usize) -> bool {
+    let mut snapshots = world.resource_mut::<Snapshots>();
+    if steps == 0 || steps > snapshots.len() {
+        return false;
+    }
+    for _ in 1..steps {
+        snapshots.snapshots.pop_newest();
+    }
+    let snapshot = snapshots.snapshots.pop_newest().unwrap();
+    snapshots.frames_since_snapshot = 0;
+
+    let spawned_since: Vec<Entity> = world.iter_entities()
+        .map(|entity| entity.id())
+        .filter(|entity| !snapshot.entities.contains(entity))
+        .collect();
+    for entity in spawned_since {
+        world.despawn(entity);
+    }
+    world.resource_scope(|world, registry: Mut<SnapshotRegistry>| {
+        for (type_id, data) in &snapshot.data {
+            if let Some(registration) = registry.registrations.iter().find(|r| r.type_id == *type_id) {
+                (registration.restore)(world, data.as_ref());
+            }
+        }
+    });
+    log::info!("rewound to the snapshot of frame {}", snapshot.frame);
+    true
+}
+
+pub fn rewind_on_key(
+    mut input_rdr: EventReader<InputEvent>,
+    bindings: Res<KeyBindings>,
+    mut snapshots: ResMut<Snapshots>,
+) {
+    if input_rdr.iter().any(|evt| bindings.is_pressed("rewind", &evt.0)) {
+        snapshots.request_rewind(1);
+    }
+}
+
+/// Apply a requested rewind, or take a snapshot every `interval` frames
+pub fn update_snapshots(world: &mut World) {
+    let requested_rewind = world.resource_mut::<Snapshots>().requested_rewind.take();
+    if let Some(steps) = requested_rewind {
+        if !rewind(world, steps) {
+            log::warn!("no snapshot to rewind {steps} step(s) to");
+        }
+        return;
+    }
+
+    let mut snapshots = world.resource_mut::<Snapshots>();
+    snapshots.frames_since_snapshot += 1;
+    if snapshots.frames_since_snapshot >= snapshots.interval {
+        take_snapshot(world);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::system::Commands;
+
+    use super::*;
+    use crate::headless::{HeadlessRunner, headless_runner};
+
+    #[test]
+    fn rewind_restores_time_but_not_the_frame_count() {
+        let world = EcsBuilder::new()
+            .add_plugin(SnapshotPlugin { interval: 2, capacity: 3 })
+            .insert_resource(HeadlessRunner { frames: 6, delta: 0.125 })
+            .add_system(|time: Res<Time>, mut snapshots: ResMut<Snapshots>| {
+                if time.frame_count == 5 {
+                    snapshots.request_rewind(1);
+                }
+            }, Update)
+            .set_runner(headless_runner)
+            .build()
+            .run();
+
+        // Back to the snapshot of frame 4 at the end of frame 5, then one more frame
+        let time = world.resource::<Time>();
+        assert_eq!(time.current, 5.0 * 0.125);
+        assert_eq!(time.frame_count, 6);
+        assert_eq!(world.resource::<Snapshots>().frames().collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[derive(Component)]
+    struct Marker;
+
+    #[test]
+    fn rewind_despawns_entities_spawned_after_the_snapshot() {
+        let mut world = EcsBuilder::new()
+            .add_plugin(SnapshotPlugin { interval: 2, capacity: 3 })
+            .insert_resource(HeadlessRunner { frames: 3, delta: 0.125 })
+            .add_system(|mut commands: Commands, time: Res<Time>, mut snapshots: ResMut<Snapshots>| {
+                match time.frame_count {
+                    1 => { commands.spawn(Marker); },
+                    // After the snapshot of frame 2, with a component snapshots don't capture
+                    3 => {
+                        commands.spawn(Marker);
+                        snapshots.request_rewind(1);
+                    },
+                    _ => {},
+                }
+            }, Update)
+            .set_runner(headless_runner)
+            .build()
+            .run();
+
+        assert_eq!(world.query::<&Marker>().iter(&world).count(), 1);
+    }
+}
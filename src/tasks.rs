@@ -0,0 +1,119 @@
+use std::{future::Future, ops::Deref};
+
+use bevy_ecs::{
+    event::Event,
+    prelude::{Component, Entity, EventWriter},
+    system::{Commands, Query, Resource},
+};
+use bevy_tasks::{AsyncComputeTaskPool, IoTaskPool, Task, TaskPool, TaskPoolBuilder};
+use futures_lite::future;
+
+use crate::ecs::{EcsBuilder, Incomplete, Plugin, PreUpdate};
+
+
+/// Starts the background task pools and inserts them as the `ComputeTasks` and `IoTasks` resources.
+///
+/// These pools are for work that spans several frames. Systems spawn a future on one of them,
+/// keep the returned `Task` (e.g. in a `PendingTask` component) and poll it in later frames.
+/// The pools are process-wide (see `Ecs`), so only the first Ecs built decides their thread counts.
+#[derive(Default)]
+pub struct TaskPoolPlugin {
+    /// Threads for CPU-heavy work like decoding and parsing. Defaults to the number of cores.
+    pub compute_threads: Option<usize>,
+    /// Threads for work that mostly waits, like reading files. Defaults to the number of cores.
+    pub io_threads: Option<usize>,
+}
+
+impl Plugin for TaskPoolPlugin {
+    fn build(&self, ecs_builder: EcsBuilder<Incomplete>) -> EcsBuilder<Incomplete> {
+        let compute = AsyncComputeTaskPool::init(|| build_pool("compute", self.compute_threads));
+        let io = IoTaskPool::init(|| build_pool("io", self.io_threads));
+        ecs_builder
+            .insert_resource(ComputeTasks(compute))
+            .insert_resource(IoTasks(io))
+    }
+}
+
+fn build_pool(name: &str, threads: Option<usize>) -> TaskPool {
+    let mut builder = TaskPoolBuilder::new().thread_name(format!("{name} task pool"));
+    if let Some(threads) = threads {
+        builder = builder.num_threads(threads);
+    }
+    builder.build()
+}
+
+/// Pool for CPU-bound background work
+#[derive(Resource, Clone, Copy)]
+pub struct ComputeTasks(&'static AsyncComputeTaskPool);
+
+impl Deref for ComputeTasks {
+    type Target = TaskPool;
+    fn deref(&self) -> &TaskPool {
+        self.0
+    }
+}
+
+/// Pool for IO-bound background work
+#[derive(Resource, Clone, Copy)]
+pub struct IoTasks(&'static IoTaskPool);
+
+impl Deref for IoTasks {
+    type Target = TaskPool;
+    fn deref(&self) -> &TaskPool {
+        self.0
+    }
+}
+
+/// Take the result of a task if it has finished, without blocking
+pub fn poll_task<T>(task: &mut Task<T>) -> Option<T> {
+    future::block_on(future::poll_once(task))
+}
+
+/// A running task whose result belongs to this entity
+#[derive(Component)]
+pub struct PendingTask<T: Send + 'static>(Option<Task<T>>);
+
+impl<T: Send + 'static> PendingTask<T> {
+    pub fn spawn(pool: &TaskPool, future: impl Future<Output = T> + Send + 'static) -> Self {
+        Self(Some(pool.spawn(future)))
+    }
+
+    pub fn new(task: Task<T>) -> Self {
+        Self(Some(task))
+    }
+
+    /// Take the result if the task has finished. The result is only returned once.
+    pub fn poll(&mut self) -> Option<T> {
+        let result = poll_task(self.0.as_mut()?)?;
+        self.0 = None;
+        Some(result)
+    }
+}
+
+/// Sent once the `PendingTask<T>` of an entity has finished. The component is removed at the same time.
+pub struct TaskCompleted<T> {
+    pub entity: Entity,
+    pub result: T,
+}
+
+impl EcsBuilder<Incomplete> {
+    /// Poll every `PendingTask<T>` at the start of each frame, sending a `TaskCompleted<T>` event
+    /// for each task that has finished
+    pub fn add_task_event<T: Event>(self) -> Self {
+        self.add_event::<TaskCompleted<T>>()
+            .add_system(poll_pending_tasks::<T>, PreUpdate)
+    }
+}
+
+pub fn poll_pending_tasks<T: Event>(
+    mut commands: Commands,
+    mut task_qry: Query<(Entity, &mut PendingTask<T>)>,
+    mut completed_wtr: EventWriter<TaskCompleted<T>>,
+) {
+    for (entity, mut task) in &mut task_qry {
+        if let Some(result) = task.poll() {
+            commands.entity(entity).remove::<PendingTask<T>>();
+            completed_wtr.send(TaskCompleted { entity, result });
+        }
+    }
+}
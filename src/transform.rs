@@ -0,0 +1,368 @@
+use bevy_ecs::{
+    prelude::{Bundle, Component, Entity, With, Without},
+    system::{Command, EntityCommands, Query},
+    world::World,
+};
+use glam::{Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::ecs::{Plugin, EcsBuilder, Incomplete, PostUpdate};
+
+
+pub struct TransformPlugin;
+impl Plugin for TransformPlugin {
+    fn build(&self, ecs_builder: EcsBuilder<Incomplete>) -> EcsBuilder<Incomplete> {
+        ecs_builder.add_system(propagate_transforms, PostUpdate)
+    }
+}
+
+/// Position, rotation and scale of an entity relative to its parent (or the world if it has none)
+#[derive(Component, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    pub fn from_xyz(x: f32, y: f32, z: f32) -> Self {
+        Self::from_translation(Vec3::new(x, y, z))
+    }
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self { translation, ..Self::IDENTITY }
+    }
+
+    pub fn from_rotation(rotation: Quat) -> Self {
+        Self { rotation, ..Self::IDENTITY }
+    }
+
+    pub fn from_scale(scale: Vec3) -> Self {
+        Self { scale, ..Self::IDENTITY }
+    }
+
+    pub fn with_translation(mut self, translation: Vec3) -> Self {
+        self.translation = translation;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn compute_matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// World-space transform of an entity. Computed from the Transforms up the hierarchy
+/// by `propagate_transforms` in PostUpdate, so it shouldn't be written to directly.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Default)]
+pub struct GlobalTransform(pub Mat4);
+
+impl GlobalTransform {
+    pub fn matrix(&self) -> Mat4 {
+        self.0
+    }
+
+    pub fn translation(&self) -> Vec3 {
+        self.0.w_axis.truncate()
+    }
+}
+
+#[derive(Bundle, Clone, Copy, Default)]
+pub struct TransformBundle {
+    pub local: Transform,
+    pub global: GlobalTransform,
+}
+
+impl TransformBundle {
+    pub fn from_transform(transform: Transform) -> Self {
+        Self {
+            local: transform,
+            global: GlobalTransform(transform.compute_matrix()),
+        }
+    }
+}
+
+/// The entity this entity's Transform is relative to.
+/// Use the `HierarchyCommands` to change it so the parent's `Children` stay in sync.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Parent(Entity);
+
+impl Parent {
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
+
+#[derive(Component, Clone, Debug, Default)]
+pub struct Children(Vec<Entity>);
+
+impl Children {
+    pub fn iter(&self) -> impl Iterator<Item = &Entity> {
+        self.0.iter()
+    }
+}
+
+pub fn propagate_transforms(
+    mut root_qry: Query<(&Transform, &mut GlobalTransform, Option<&Children>), Without<Parent>>,
+    mut child_qry: Query<(&Transform, &mut GlobalTransform, Option<&Children>), With<Parent>>,
+) {
+    for (transform, mut global, children) in &mut root_qry {
+        global.0 = transform.compute_matrix();
+        if let Some(children) = children {
+            for child in children.iter() {
+                propagate_recursive(global.0, *child, &mut child_qry);
+            }
+        }
+    }
+}
+
+fn propagate_recursive(
+    parent_matrix: Mat4,
+    entity: Entity,
+    child_qry: &mut Query<(&Transform, &mut GlobalTransform, Option<&Children>), With<Parent>>,
+) {
+    let (matrix, children) = match child_qry.get_mut(entity) {
+        Ok((transform, mut global, children)) => {
+            global.0 = parent_matrix * transform.compute_matrix();
+            (global.0, children.cloned())
+        },
+        // Entities without a transform break the chain
+        Err(_) => return,
+    };
+
+    if let Some(children) = children {
+        for child in children.iter() {
+            propagate_recursive(matrix, *child, child_qry);
+        }
+    }
+}
+
+/// Hierarchy operations that keep `Parent` and `Children` consistent
+pub trait HierarchyCommands {
+    /// Make this entity a child of `parent`, removing it from its previous parent's Children
+    fn set_parent(&mut self, parent: Entity) -> &mut Self;
+    /// Detach this entity from its parent, making it a root
+    fn remove_parent(&mut self) -> &mut Self;
+    /// Despawn this entity along with all of its descendants
+    fn despawn_recursive(self);
+}
+
+impl HierarchyCommands for EntityCommands<'_, '_, '_> {
+    fn set_parent(&mut self, parent: Entity) -> &mut Self {
+        let child = self.id();
+        self.commands().add(SetParent { child, parent });
+        self
+    }
+
+    fn remove_parent(&mut self) -> &mut Self {
+        let child = self.id();
+        self.commands().add(RemoveParent { child });
+        self
+    }
+
+    fn despawn_recursive(mut self) {
+        let entity = self.id();
+        self.commands().add(DespawnRecursive { entity });
+    }
+}
+
+pub struct SetParent {
+    pub child: Entity,
+    pub parent: Entity,
+}
+
+impl Command for SetParent {
+    /// Rejects the change, logging an error, if either entity doesn't exist (e.g. was despawned by an earlier command),
+    /// or if `parent` is `child` or one of its descendants, as the cycle would be skipped by `propagate_transforms`
+    fn write(self, world: &mut World) {
+        if let Some(missing) = [self.child, self.parent].into_iter().find(|e| world.get_entity(*e).is_none()) {
+            log::error!("can't make {:?} a child of {:?}, {missing:?} doesn't exist", self.child, self.parent);
+            return;
+        }
+        if is_ancestor_or_self(world, self.child, self.parent) {
+            log::error!(
+                "can't make {:?} a child of {:?}, which is the entity itself or one of its descendants",
+                self.child, self.parent
+            );
+            return;
+        }
+        detach_from_parent(world, self.child);
+
+        world.entity_mut(self.child).insert(Parent(self.parent));
+        let mut parent = world.entity_mut(self.parent);
+        match parent.get_mut::<Children>() {
+            Some(mut children) => children.0.push(self.child),
+            None => {
+                parent.insert(Children(vec![self.child]));
+            },
+        }
+    }
+}
+
+pub struct RemoveParent {
+    pub child: Entity,
+}
+
+impl Command for RemoveParent {
+    fn write(self, world: &mut World) {
+        if world.get_entity(self.child).is_none() {
+            log::error!("can't remove the parent of {:?}, which doesn't exist", self.child);
+            return;
+        }
+        detach_from_parent(world, self.child);
+        world.entity_mut(self.child).remove::<Parent>();
+    }
+}
+
+pub struct DespawnRecursive {
+    pub entity: Entity,
+}
+
+impl Command for DespawnRecursive {
+    fn write(self, world: &mut World) {
+        detach_from_parent(world, self.entity);
+        despawn_with_children(world, self.entity);
+    }
+}
+
+/// Whether `ancestor` is `entity` or one of its ancestors
+fn is_ancestor_or_self(world: &World, ancestor: Entity, entity: Entity) -> bool {
+    let mut current = Some(entity);
+    while let Some(entity) = current {
+        if entity == ancestor {
+            return true;
+        }
+        current = world.get::<Parent>(entity).map(Parent::get);
+    }
+    false
+}
+
+/// Remove `child` from its parent's Children, leaving its Parent component untouched
+fn detach_from_parent(world: &mut World, child: Entity) {
+    let Some(parent) = world.get::<Parent>(child).map(Parent::get) else { return };
+    if let Some(mut children) = world.get_mut::<Children>(parent) {
+        children.0.retain(|e| *e != child);
+    }
+}
+
+fn despawn_with_children(world: &mut World, entity: Entity) {
+    if let Some(children) = world.get_mut::<Children>(entity).map(|mut c| std::mem::take(&mut c.0)) {
+        for child in children {
+            despawn_with_children(world, child);
+        }
+    }
+    world.despawn(entity);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::schedule::Schedule;
+
+    use super::*;
+
+    fn propagate(world: &mut World) {
+        let mut schedule = Schedule::new();
+        schedule.add_system(propagate_transforms);
+        schedule.run(world);
+    }
+
+    fn spawn(world: &mut World, x: f32) -> Entity {
+        world.spawn(TransformBundle::from_transform(Transform::from_xyz(x, 0.0, 0.0))).id()
+    }
+
+    fn global_x(world: &World, entity: Entity) -> f32 {
+        world.get::<GlobalTransform>(entity).unwrap().translation().x
+    }
+
+    #[test]
+    fn nested_transforms_add_up() {
+        let mut world = World::new();
+        let root = spawn(&mut world, 1.0);
+        let child = spawn(&mut world, 2.0);
+        let grandchild = spawn(&mut world, 4.0);
+        SetParent { child, parent: root }.write(&mut world);
+        SetParent { child: grandchild, parent: child }.write(&mut world);
+
+        propagate(&mut world);
+
+        assert_eq!(global_x(&world, root), 1.0);
+        assert_eq!(global_x(&world, child), 3.0);
+        assert_eq!(global_x(&world, grandchild), 7.0);
+    }
+
+    #[test]
+    fn reparenting_moves_the_subtree() {
+        let mut world = World::new();
+        let a = spawn(&mut world, 1.0);
+        let b = spawn(&mut world, 10.0);
+        let child = spawn(&mut world, 2.0);
+        let grandchild = spawn(&mut world, 4.0);
+        SetParent { child, parent: a }.write(&mut world);
+        SetParent { child: grandchild, parent: child }.write(&mut world);
+        propagate(&mut world);
+
+        SetParent { child, parent: b }.write(&mut world);
+        propagate(&mut world);
+
+        assert_eq!(world.get::<Parent>(child).unwrap().get(), b);
+        assert!(world.get::<Children>(a).unwrap().iter().next().is_none());
+        assert_eq!(world.get::<Children>(b).unwrap().iter().copied().collect::<Vec<_>>(), vec![child]);
+        assert_eq!(global_x(&world, child), 12.0);
+        assert_eq!(global_x(&world, grandchild), 16.0);
+    }
+
+    #[test]
+    fn reparenting_under_a_descendant_is_rejected() {
+        let mut world = World::new();
+        let root = spawn(&mut world, 1.0);
+        let child = spawn(&mut world, 2.0);
+        let grandchild = spawn(&mut world, 4.0);
+        SetParent { child, parent: root }.write(&mut world);
+        SetParent { child: grandchild, parent: child }.write(&mut world);
+
+        SetParent { child: root, parent: grandchild }.write(&mut world);
+        SetParent { child, parent: child }.write(&mut world);
+        propagate(&mut world);
+
+        assert!(world.get::<Parent>(root).is_none());
+        assert_eq!(world.get::<Parent>(child).unwrap().get(), root);
+        assert_eq!(global_x(&world, grandchild), 7.0);
+    }
+
+    #[test]
+    fn commands_on_despawned_entities_are_skipped() {
+        let mut world = World::new();
+        let a = spawn(&mut world, 1.0);
+        let b = spawn(&mut world, 2.0);
+        DespawnRecursive { entity: a }.write(&mut world);
+
+        SetParent { child: b, parent: a }.write(&mut world);
+        SetParent { child: a, parent: b }.write(&mut world);
+        RemoveParent { child: a }.write(&mut world);
+
+        assert!(world.get::<Parent>(b).is_none());
+        assert!(world.get::<Children>(b).is_none());
+    }
+}
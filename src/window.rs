@@ -1,10 +1,15 @@
+use std::collections::HashMap;
 use std::ffi::{CString, self};
 use std::num::NonZeroU32;
+use std::path::PathBuf;
 
-use bevy_ecs::system::Resource;
+use bevy_ecs::prelude::{Component, Entity, EventReader, EventWriter};
+use bevy_ecs::{change_detection::DetectChanges, system::{Commands, NonSendMut, Query, Res, ResMut, Resource}, world::World};
+use serde::{Deserialize, Serialize};
 use winit::dpi::PhysicalSize;
 use winit::event_loop::{EventLoop, EventLoopWindowTarget};
-use winit::window::WindowBuilder;
+use winit::monitor::MonitorHandle;
+use winit::window::{CursorGrabMode, Fullscreen, Theme, WindowBuilder, WindowId};
 
 use raw_window_handle::HasRawWindowHandle;
 
@@ -16,14 +21,319 @@ use glutin::surface::{SwapInterval, Surface, WindowSurface};
 
 use glutin_winit::{self, DisplayBuilder, GlWindow};
 
+use crate::ecs::{Plugin, EcsBuilder, Incomplete, AppExit, PreUpdate, Update, Last};
 
-#[derive(Resource, Clone)]
+
+pub struct WindowPlugin {
+    /// Exit as soon as the primary window is asked to close, and despawn secondary windows asked to close.
+    /// Disable this to veto or delay closing by handling `WindowCloseRequested` yourself.
+    pub exit_on_close: bool,
+}
+
+impl Default for WindowPlugin {
+    fn default() -> Self {
+        Self { exit_on_close: true }
+    }
+}
+
+impl Plugin for WindowPlugin {
+    fn build(&self, ecs_builder: EcsBuilder<Incomplete>) -> EcsBuilder<Incomplete> {
+        let ecs_builder = ecs_builder
+            .init_resource::<WindowInfo>()
+            .init_resource::<GraphicsSettings>()
+            .init_resource::<GraphicsCapabilities>()
+            .add_event::<WindowCloseRequested>()
+            .add_event::<WindowResized>()
+            .add_event::<WindowScaleFactorChanged>()
+            .add_event::<WindowFocused>()
+            .add_event::<WindowMoved>()
+            .add_event::<CursorEntered>()
+            .add_event::<CursorLeft>()
+            .add_event::<FileDropped>()
+            .add_event::<WindowThemeChanged>()
+            .add_system(update_window_sizes, PreUpdate)
+            .add_system(apply_window_info, Last)
+            .add_system(apply_graphics_settings, Last);
+        if self.exit_on_close {
+            ecs_builder.add_system(exit_on_close_request, Update)
+        } else {
+            ecs_builder
+        }
+    }
+}
+
+/// Sent by the runner when the user tries to close a window (e.g. with the title bar's close button)
+#[derive(Debug, Clone, Copy)]
+pub struct WindowCloseRequested {
+    pub window: WindowRef,
+}
+
+pub fn exit_on_close_request(
+    mut commands: Commands,
+    mut close_rdr: EventReader<WindowCloseRequested>,
+    mut exit_wtr: EventWriter<AppExit>,
+) {
+    for evt in close_rdr.iter() {
+        match evt.window {
+            WindowRef::Primary => exit_wtr.send(AppExit),
+            WindowRef::Entity(entity) => commands.entity(entity).despawn(),
+        }
+    }
+}
+
+/// Sent by the runner when a window's inner size changes. Zero sizes (e.g. minimized windows) aren't reported.
+#[derive(Debug, Clone, Copy)]
+pub struct WindowResized {
+    pub window: WindowRef,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Sent by the runner when a window moves to a monitor with a different DPI, or the DPI setting changes
+#[derive(Debug, Clone, Copy)]
+pub struct WindowScaleFactorChanged {
+    pub window: WindowRef,
+    pub scale_factor: f64,
+}
+
+/// Sent by the runner when a window gains or loses keyboard focus
+#[derive(Debug, Clone, Copy)]
+pub struct WindowFocused {
+    pub window: WindowRef,
+    pub focused: bool,
+}
+
+/// Sent by the runner when a window moves, with the new position of its top-left corner on the desktop
+#[derive(Debug, Clone, Copy)]
+pub struct WindowMoved {
+    pub window: WindowRef,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Sent by the runner when the cursor enters a window
+#[derive(Debug, Clone, Copy)]
+pub struct CursorEntered {
+    pub window: WindowRef,
+}
+
+/// Sent by the runner when the cursor leaves a window
+#[derive(Debug, Clone, Copy)]
+pub struct CursorLeft {
+    pub window: WindowRef,
+}
+
+/// Sent by the runner for every file dropped onto a window
+#[derive(Debug, Clone)]
+pub struct FileDropped {
+    pub window: WindowRef,
+    pub path: PathBuf,
+}
+
+/// Sent by the runner when the system switches between the light and dark theme
+#[derive(Debug, Clone, Copy)]
+pub struct WindowThemeChanged {
+    pub window: WindowRef,
+    pub theme: Theme,
+}
+
+/// Resize the surfaces and update the WindowInfo and SecondaryWindow sizes from WindowResized events,
+/// which the renderer's viewports follow
+pub fn update_window_sizes(
+    mut resized_rdr: EventReader<WindowResized>,
+    mut window: Option<NonSendMut<Window>>,
+    mut window_info: ResMut<WindowInfo>,
+    mut secondary_qry: Query<&mut SecondaryWindow>,
+) {
+    for evt in resized_rdr.iter() {
+        let size = PhysicalSize::new(evt.width, evt.height);
+        if let Some(window) = &mut window {
+            window.resize(evt.window, size);
+            // Already the window's size, so `apply_window_info` mustn't apply it back
+            if evt.window == WindowRef::Primary {
+                window.set_applied_size(size);
+            }
+        }
+        match evt.window {
+            WindowRef::Primary => {
+                window_info.width = evt.width;
+                window_info.height = evt.height;
+            },
+            WindowRef::Entity(entity) => if let Ok(mut secondary) = secondary_qry.get_mut(entity) {
+                secondary.info.width = evt.width;
+                secondary.info.height = evt.height;
+            },
+        }
+    }
+}
+
+/// One of the windows: the primary one, created by the runner and configured by the WindowInfo resource,
+/// or the entity of a SecondaryWindow
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum WindowRef {
+    #[default]
+    Primary,
+    Entity(Entity),
+}
+
+/// Spawning an entity with this component opens another window, sharing the primary window's GL context,
+/// so models and textures can be drawn in any window. Despawning the entity closes the window.
+///
+/// The window is opened by the runner at the end of the next frame, and only drawn into by cameras
+/// whose RenderTarget is this entity. `info.width` and `info.height` follow the window's size
+/// (see `update_window_sizes`);
+/// other changes to `info` are not applied after the window is opened.
+#[derive(Component, Clone, Default)]
+pub struct SecondaryWindow {
+    pub info: WindowInfo,
+}
+
+
+/// Settings of the window. Changes made to the resource at runtime are applied to the window
+/// at the end of the frame by `apply_window_info`.
+#[derive(Resource, Clone, PartialEq)]
 pub struct WindowInfo {
     pub width: u32,
     pub height: u32,
-    pub title: &'static str,
+    pub title: String,
+    pub resizable: bool,
+    pub mode: WindowMode,
+    /// Show the title bar and borders
+    pub decorations: bool,
+    /// Let the desktop show through where the framebuffer's alpha is below 1.
+    /// Only honored by some platforms, and only picked when the window is created.
+    pub transparent: bool,
+    /// Keep the cursor inside the window, e.g. for mouse-look
+    pub cursor_grab: CursorGrab,
+    pub cursor_visible: bool,
 }
 
+impl Default for WindowInfo {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+            title: "engine".into(),
+            resizable: false,
+            mode: WindowMode::Windowed,
+            decorations: true,
+            transparent: true,
+            cursor_grab: CursorGrab::None,
+            cursor_visible: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowMode {
+    #[default]
+    Windowed,
+    /// Covers the whole monitor without changing its video mode
+    BorderlessFullscreen,
+    /// Takes over the monitor, switching to the video mode closest to the window size
+    Fullscreen,
+}
+
+/// How the cursor is kept inside the primary window.
+/// Platforms support only one of Confined and Locked, the other one is used as a fallback.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CursorGrab {
+    #[default]
+    None,
+    /// The cursor can move but not leave the window. Not supported on macOS.
+    Confined,
+    /// The cursor stays where it is. Not supported on X11 and Windows.
+    Locked,
+}
+
+impl CursorGrab {
+    fn mode(self) -> CursorGrabMode {
+        match self {
+            CursorGrab::None => CursorGrabMode::None,
+            CursorGrab::Confined => CursorGrabMode::Confined,
+            CursorGrab::Locked => CursorGrabMode::Locked,
+        }
+    }
+}
+
+/// Settings used when creating the GL config and surface.
+/// Changes made to the resource at runtime are applied at the end of the frame by `apply_graphics_settings`,
+/// and what could actually be applied is reported in the GraphicsCapabilities resource.
+#[derive(Resource, Clone, PartialEq)]
+pub struct GraphicsSettings {
+    pub vsync: VsyncMode,
+    /// Requested number of MSAA samples (0 to disable).
+    /// A config with exactly this many samples is picked when the window is created if there is one,
+    /// otherwise the closest one. The config isn't recreated afterwards, so changing the count at runtime
+    /// can only turn MSAA off, or back on with the sample count of the picked config.
+    pub msaa_samples: u8,
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        Self {
+            vsync: VsyncMode::On,
+            msaa_samples: 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VsyncMode {
+    /// Swap buffers as soon as a frame is ready, which may tear
+    Off,
+    /// Wait for the next vertical blank before swapping buffers
+    #[default]
+    On,
+    /// Like On, but late frames are swapped immediately instead of waiting another refresh.
+    /// glutin can't request it yet, so On is used instead.
+    Adaptive,
+}
+
+/// What the GL config and surface actually provide, compared to the requested GraphicsSettings.
+/// Updated by the runner when the window is created, and whenever GraphicsSettings changes.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct GraphicsCapabilities {
+    /// Sample counts of the configs the display offered, ascending
+    pub available_msaa_samples: Vec<u8>,
+    /// Sample count of the picked config
+    pub config_msaa_samples: u8,
+    /// Number of MSAA samples in use, 0 if disabled
+    pub msaa_samples: u8,
+    /// Vsync mode in use, or None if setting it failed since the surface was created,
+    /// which leaves the driver's default in use
+    pub vsync: Option<VsyncMode>,
+    /// Why the current settings differ from the requested ones, if they do
+    pub fallbacks: Vec<String>,
+}
+
+/// Applies the changes made to the GraphicsSettings resource to the window's surface
+pub fn apply_graphics_settings(
+    window: Option<NonSendMut<Window>>,
+    graphics: Res<GraphicsSettings>,
+    mut capabilities: ResMut<GraphicsCapabilities>,
+) {
+    if let Some(mut window) = window {
+        if graphics.is_changed() && window.apply_graphics(&graphics) {
+            *capabilities = window.capabilities().clone();
+        }
+    }
+}
+
+/// Applies the changes made to the WindowInfo resource to the window
+pub fn apply_window_info(window: Option<NonSendMut<Window>>, window_info: Res<WindowInfo>) {
+    if let Some(mut window) = window {
+        if window_info.is_changed() {
+            window.apply(&window_info);
+        }
+    }
+}
+
+/// The winit window and its GL context and surface.
+/// The winit runner inserts it as a NonSend resource, as it must stay on the main thread.
 pub struct Window {
     gl_config: Config,
     gl_display: Display,
@@ -31,10 +341,35 @@ pub struct Window {
     gl_context: Option<PossiblyCurrentContext>,
     gl_surface: Option<Surface<WindowSurface>>,
     window: Option<winit::window::Window>,
+    // WindowInfo the window currently matches, to only apply what changed
+    applied: WindowInfo,
+    // Grab mode in effect, which may be the fallback of the requested one
+    cursor_grab: CursorGrab,
+    // Same for the GraphicsSettings, None until the surface exists
+    applied_graphics: Option<GraphicsSettings>,
+    capabilities: GraphicsCapabilities,
+    secondary: HashMap<Entity, SecondarySurface>,
+}
+
+/// A SecondaryWindow's winit window and surface. The surface is released while the app is suspended.
+struct SecondarySurface {
+    window: winit::window::Window,
+    gl_surface: Option<Surface<WindowSurface>>,
+}
+
+/// Open the windows of the SecondaryWindow entities spawned since the last call,
+/// and close the ones of despawned entities. Called by the runner, which owns the window target.
+pub fn sync_secondary_windows(world: &mut World, window_target: &EventLoopWindowTarget<()>) {
+    let windows: Vec<(Entity, WindowInfo)> = world.query::<(Entity, &SecondaryWindow)>().iter(world)
+        .map(|(entity, window)| (entity, window.info.clone()))
+        .collect();
+    if let Some(mut window) = world.get_non_send_resource_mut::<Window>() {
+        window.sync_secondary(window_target, &windows);
+    }
 }
 
 impl Window {
-    pub fn new(window_info: &WindowInfo) -> (Self, EventLoop<()>) {
+    pub fn new(window_info: &WindowInfo, graphics: &GraphicsSettings) -> (Self, EventLoop<()>) {
         let event_loop = EventLoop::new();
 
         // Only windows requires the window to be present before creating the display.
@@ -44,7 +379,7 @@ impl Window {
         // this condition and always pass the window builder.
         let window_builder =
             if cfg!(wgl_backend) {
-                Some(get_window_builder(window_info))
+                Some(get_window_builder(window_info, event_loop.primary_monitor()))
             } else { None };
 
         // The template will match only the configurations supporting rendering
@@ -55,31 +390,39 @@ impl Window {
         // that, because we can query only one config at a time on it, but all
         // normal platforms will return multiple configs, so we can find the config
         // with transparency ourselves inside the `reduce`.
-        let template =
-            ConfigTemplateBuilder::new().with_alpha_size(8).with_transparency(cfg!(cgl_backend));
+        let template = ConfigTemplateBuilder::new()
+            .with_alpha_size(8)
+            .with_transparency(cfg!(cgl_backend) && window_info.transparent);
 
         let display_builder = DisplayBuilder::new().with_window_builder(window_builder);
 
+        let mut available_msaa_samples = Vec::new();
         let (window, gl_config) = display_builder
             .build(&event_loop, template, |configs| {
-                // Find the config with the maximum number of samples, so our triangle will
-                // be smooth.
-                configs
-                    .reduce(|accum, config| {
-                        let transparency_check = config.supports_transparency().unwrap_or(false)
-                            & !accum.supports_transparency().unwrap_or(false);
-
-                        if transparency_check || config.num_samples() > accum.num_samples() {
-                            config
-                        } else {
-                            accum
-                        }
-                    })
+                let configs: Vec<Config> = configs.collect();
+                available_msaa_samples = configs.iter().map(|config| config.num_samples()).collect();
+                available_msaa_samples.sort_unstable();
+                available_msaa_samples.dedup();
+
+                // Find the config with the number of samples closest to the requested one
+                // (the exact one if available), preferring configs that support transparency if it's wanted.
+                let requested_samples = graphics.msaa_samples as i32;
+                configs.into_iter()
+                    .min_by_key(|config| (
+                        (config.num_samples() as i32 - requested_samples).abs(),
+                        window_info.transparent != config.supports_transparency().unwrap_or(false),
+                    ))
                     .unwrap()
             })
             .unwrap();
 
-        println!("Picked a config with {} samples", gl_config.num_samples());
+        log::info!("Picked a config with {} samples", gl_config.num_samples());
+
+        let capabilities = GraphicsCapabilities {
+            available_msaa_samples,
+            config_msaa_samples: gl_config.num_samples(),
+            ..Default::default()
+        };
 
         let raw_window_handle = window.as_ref().map(|window| window.raw_window_handle());
 
@@ -124,17 +467,86 @@ impl Window {
                 gl_context: None,
                 gl_surface: None,
                 window,
+                // The cursor can only be grabbed once the window exists, so leave it to `apply`
+                applied: WindowInfo {
+                    cursor_grab: CursorGrab::None,
+                    cursor_visible: true,
+                    ..window_info.clone()
+                },
+                cursor_grab: CursorGrab::None,
+                applied_graphics: None,
+                capabilities,
+                secondary: HashMap::new(),
             },
             event_loop
         )
     }
     
+    /// Swap the buffers of every window, leaving the primary window's surface current
     pub fn swap_buffers(&self) {
-        if let (Some(gl_context), Some(gl_surface))
-            = (&self.gl_context, &self.gl_surface) {
+        let Some(gl_context) = &self.gl_context else { return };
+        for secondary_surface in self.secondary.values().filter_map(|secondary| secondary.gl_surface.as_ref()) {
+            if let Err(err) = gl_context.make_current(secondary_surface)
+                .and_then(|()| secondary_surface.swap_buffers(gl_context))
+            {
+                log::error!("failed to present a secondary window: {err}");
+            }
+        }
+        if let Some(gl_surface) = &self.gl_surface {
+            if !self.secondary.is_empty() {
+                gl_context.make_current(gl_surface).unwrap();
+            }
             gl_surface.swap_buffers(gl_context).unwrap();
         }
     }
+
+    /// Make `target`'s surface the one drawn into. Returns false if that window isn't open.
+    pub fn make_current(&self, target: WindowRef) -> bool {
+        let Some(gl_context) = &self.gl_context else { return false };
+        self.surface(target).is_some_and(|gl_surface| gl_context.make_current(gl_surface).is_ok())
+    }
+
+    fn surface(&self, target: WindowRef) -> Option<&Surface<WindowSurface>> {
+        match target {
+            WindowRef::Primary => self.gl_surface.as_ref(),
+            WindowRef::Entity(entity) => self.secondary.get(&entity).and_then(|secondary| secondary.gl_surface.as_ref()),
+        }
+    }
+
+    /// Which window a winit event comes from
+    pub fn window_ref(&self, id: WindowId) -> Option<WindowRef> {
+        if self.window.as_ref().is_some_and(|window| window.id() == id) {
+            return Some(WindowRef::Primary);
+        }
+        self.secondary.iter()
+            .find(|(_, secondary)| secondary.window.id() == id)
+            .map(|(entity, _)| WindowRef::Entity(*entity))
+    }
+
+    fn sync_secondary(&mut self, window_target: &EventLoopWindowTarget<()>, windows: &[(Entity, WindowInfo)]) {
+        // Dropping the winit window closes it
+        self.secondary.retain(|entity, _| windows.iter().any(|(e, _)| e == entity));
+
+        let (Some(gl_context), Some(gl_surface)) = (&self.gl_context, &self.gl_surface) else { return };
+        for (entity, window_info) in windows {
+            if self.secondary.contains_key(entity) {
+                continue;
+            }
+            let window_builder = get_window_builder(window_info, window_target.primary_monitor());
+            let window = match glutin_winit::finalize_window(window_target, window_builder, &self.gl_config) {
+                Ok(window) => window,
+                Err(err) => {
+                    log::error!("failed to open window \"{}\": {err}", window_info.title);
+                    continue;
+                },
+            };
+            let secondary_surface = create_secondary_surface(&self.gl_config, gl_context, &window);
+            gl_context.make_current(gl_surface).unwrap();
+            if secondary_surface.is_some() {
+                self.secondary.insert(*entity, SecondarySurface { window, gl_surface: secondary_surface });
+            }
+        }
+    }
     
     pub fn get_proc_address(&self, symbol: &str) -> *const ffi::c_void {
         let symbol = CString::new(symbol).unwrap();
@@ -144,12 +556,13 @@ impl Window {
     pub fn resume(&mut self,
         window_target: &EventLoopWindowTarget<()>,
         window_info: &WindowInfo,
+        graphics: &GraphicsSettings,
     ) {
         #[cfg(android_platform)]
-        println!("Android window available");
+        log::info!("Android window available");
 
         let window = self.window.take().unwrap_or_else(|| {
-            let window_builder = get_window_builder(window_info);
+            let window_builder = get_window_builder(window_info, window_target.primary_monitor());
             glutin_winit::finalize_window(window_target, window_builder, &self.gl_config)
                 .unwrap()
         });
@@ -163,30 +576,102 @@ impl Window {
         let gl_context =
             self.not_current_gl_context.take().unwrap().make_current(&gl_surface).unwrap();
 
-        // Try setting vsync.
-        if let Err(res) = gl_surface
-            .set_swap_interval(&gl_context, SwapInterval::Wait(NonZeroU32::new(1).unwrap()))
-        {
-            eprintln!("Error setting vsync: {res:?}");
-        }
-        
         assert!(self.gl_context.replace(gl_context).is_none()
             && self.gl_surface.replace(gl_surface).is_none()
             && self.window.replace(window).is_none()
         );
         
+        // Recreate the surfaces of the secondary windows released by `suspend`
+        let (gl_context, gl_surface) = (self.gl_context.as_ref().unwrap(), self.gl_surface.as_ref().unwrap());
+        for secondary in self.secondary.values_mut() {
+            secondary.gl_surface = create_secondary_surface(&self.gl_config, gl_context, &secondary.window);
+        }
+        gl_context.make_current(gl_surface).unwrap();
+
         // Load OpenGL function pointers
         gl::load_with(|symbol| self.get_proc_address(symbol));
         
+        // Set vsync and MSAA, reporting what was applied in the capabilities
+        self.applied_graphics = None;
+        self.capabilities.vsync = None;
+        self.apply_graphics(graphics);
+    }
+
+    /// What the config and surface provide, see GraphicsCapabilities
+    pub fn capabilities(&self) -> &GraphicsCapabilities {
+        &self.capabilities
+    }
+
+    /// Apply the vsync mode and MSAA sample count of `graphics` if they differ from the current ones.
+    /// Returns false if nothing was applied, e.g. the surface doesn't exist yet.
+    pub fn apply_graphics(&mut self, graphics: &GraphicsSettings) -> bool {
+        let (Some(gl_context), Some(gl_surface)) = (&self.gl_context, &self.gl_surface) else { return false };
+        let applied = self.applied_graphics.as_ref();
+        if applied == Some(graphics) {
+            return false;
+        }
+        let capabilities = &mut self.capabilities;
+        capabilities.fallbacks.clear();
+
+        if applied.map(|applied| applied.vsync) != Some(graphics.vsync) {
+            let (swap_interval, vsync) = match graphics.vsync {
+                VsyncMode::Off => (SwapInterval::DontWait, VsyncMode::Off),
+                // glutin only exposes positive swap intervals, adaptive vsync needs a negative one
+                VsyncMode::On | VsyncMode::Adaptive => (SwapInterval::Wait(NonZeroU32::new(1).unwrap()), VsyncMode::On),
+            };
+            // On failure the surface keeps its previous swap interval, so the vsync in use doesn't change
+            match gl_surface.set_swap_interval(gl_context, swap_interval) {
+                Ok(()) => capabilities.vsync = Some(vsync),
+                Err(err) => capabilities.fallbacks.push(format!("failed to set vsync: {err}")),
+            }
+        }
+        match capabilities.vsync {
+            Some(vsync) if vsync == graphics.vsync => {},
+            Some(vsync) => capabilities.fallbacks.push(format!(
+                "vsync {:?} requested, using {vsync:?}", graphics.vsync
+            )),
+            None => capabilities.fallbacks.push(format!(
+                "vsync {:?} requested, using the driver's default", graphics.vsync
+            )),
+        }
+
+        // The sample count is fixed by the config, so MSAA can only be toggled
+        let msaa_enabled = graphics.msaa_samples > 0 && capabilities.config_msaa_samples > 0;
+        unsafe {
+            if msaa_enabled {
+                gl::Enable(gl::MULTISAMPLE);
+            } else {
+                gl::Disable(gl::MULTISAMPLE);
+            }
+        }
+        capabilities.msaa_samples = if msaa_enabled { capabilities.config_msaa_samples } else { 0 };
+        if capabilities.msaa_samples != graphics.msaa_samples {
+            capabilities.fallbacks.push(format!(
+                "{} MSAA samples requested, using {}: the sample count is fixed by the config picked \
+                when the window was created, which has {} (available: {:?})",
+                graphics.msaa_samples, capabilities.msaa_samples,
+                capabilities.config_msaa_samples, capabilities.available_msaa_samples
+            ));
+        }
+
+        for fallback in &capabilities.fallbacks {
+            log::warn!("{fallback}");
+        }
+        self.applied_graphics = Some(graphics.clone());
+        true
     }
     
     pub fn suspend(&mut self) {
         // This event is only raised on Android, where the backing NativeWindow for a GL
         // Surface can appear and disappear at any moment.
-        println!("Android window removed");
+        log::info!("Android window removed");
 
-        // Destroy the GL Surface and un-current the GL Context before ndk-glue releases
-        // the window back to the system.
+        // Destroy the GL Surfaces and un-current the GL Context before ndk-glue releases
+        // the window back to the system. `resume` creates them again.
+        for secondary in self.secondary.values_mut() {
+            secondary.gl_surface = None;
+        }
+        self.gl_surface = None;
         let gl_context = self.gl_context.take().unwrap();
         assert!(self.not_current_gl_context
             .replace(gl_context.make_not_current().unwrap())
@@ -194,13 +679,82 @@ impl Window {
         );
     }
     
-    pub fn resize(&self, size: PhysicalSize<u32>) {
+    /// Apply the settings of `window_info` that differ from the current ones
+    pub fn apply(&mut self, window_info: &WindowInfo) {
+        let Some(window) = &self.window else { return };
+        let applied = &self.applied;
+
+        if window_info.title != applied.title {
+            window.set_title(&window_info.title);
+        }
+        if window_info.resizable != applied.resizable {
+            window.set_resizable(window_info.resizable);
+        }
+        if window_info.decorations != applied.decorations {
+            window.set_decorations(window_info.decorations);
+        }
+        if window_info.transparent != applied.transparent {
+            window.set_transparent(window_info.transparent);
+        }
+        if window_info.mode != applied.mode {
+            window.set_fullscreen(get_fullscreen(window_info, window.current_monitor()));
+        }
+        let size_changed = (window_info.width, window_info.height) != (applied.width, applied.height);
+        if size_changed && window_info.mode == WindowMode::Windowed {
+            window.set_inner_size(PhysicalSize::new(window_info.width, window_info.height));
+        }
+        let cursor_visible_changed = window_info.cursor_visible != applied.cursor_visible;
+        if window_info.cursor_grab != applied.cursor_grab {
+            self.set_cursor_grab(window_info.cursor_grab);
+        }
+        if cursor_visible_changed {
+            self.set_cursor_visible(window_info.cursor_visible);
+        }
+
+        self.applied = window_info.clone();
+    }
+
+    /// Grab mode of the cursor in effect, see `WindowInfo::cursor_grab`
+    pub fn cursor_grab(&self) -> CursorGrab {
+        self.cursor_grab
+    }
+
+    /// Grab the cursor with `grab`, or the other grab mode if the platform doesn't support it.
+    /// Returns the mode in effect, which stays the previous one if neither can be set.
+    pub fn set_cursor_grab(&mut self, grab: CursorGrab) -> CursorGrab {
+        let Some(window) = &self.window else { return self.cursor_grab };
+        let fallback = match grab {
+            CursorGrab::None => CursorGrab::None,
+            CursorGrab::Confined => CursorGrab::Locked,
+            CursorGrab::Locked => CursorGrab::Confined,
+        };
+        match [grab, fallback].into_iter().find(|grab| window.set_cursor_grab(grab.mode()).is_ok()) {
+            Some(grab) => self.cursor_grab = grab,
+            None => log::warn!("failed to set the cursor grab to {grab:?}, keeping {:?}", self.cursor_grab),
+        }
+        self.cursor_grab
+    }
+
+    /// Show or hide the cursor while it's over the primary window
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        if let Some(window) = &self.window {
+            window.set_cursor_visible(visible);
+        }
+    }
+
+    /// Record a size change made by the user or the window manager, so it isn't applied back
+    pub fn set_applied_size(&mut self, size: PhysicalSize<u32>) {
+        self.applied.width = size.width;
+        self.applied.height = size.height;
+    }
+
+    pub fn resize(&self, target: WindowRef, size: PhysicalSize<u32>) {
         // Some platforms like EGL require resizing GL surface to update the size
         // Notable platforms here are Wayland and macOS, other don't require it
         // and the function is no-op, but it's wise to resize it for portability
         // reasons.
         if let (Some(gl_context), Some(gl_surface))
-            = (&self.gl_context, &self.gl_surface) {
+            = (&self.gl_context, self.surface(target)) {
             gl_surface.resize(
                 gl_context,
                 NonZeroU32::new(size.width).unwrap(),
@@ -210,9 +764,56 @@ impl Window {
     }
 }
 
-fn get_window_builder(window_info: &WindowInfo) -> WindowBuilder {
+/// Create a surface for a secondary window, leaving it current. Errors are logged.
+fn create_secondary_surface(
+    gl_config: &Config,
+    gl_context: &PossiblyCurrentContext,
+    window: &winit::window::Window,
+) -> Option<Surface<WindowSurface>> {
+    let attrs = window.build_surface_attributes(<_>::default());
+    let gl_surface = match unsafe { gl_config.display().create_window_surface(gl_config, &attrs) } {
+        Ok(gl_surface) => gl_surface,
+        Err(err) => {
+            log::error!("failed to create a surface for window \"{}\": {err}", window.title());
+            return None;
+        },
+    };
+
+    // Only the primary window waits for vsync, otherwise every window would add a wait per frame
+    if gl_context.make_current(&gl_surface).is_ok() {
+        let _ = gl_surface.set_swap_interval(gl_context, SwapInterval::DontWait);
+    }
+    Some(gl_surface)
+}
+
+fn get_window_builder(window_info: &WindowInfo, monitor: Option<MonitorHandle>) -> WindowBuilder {
     WindowBuilder::new()
+        .with_title(window_info.title.as_str())
         .with_inner_size(PhysicalSize::new(window_info.width, window_info.height))
-        .with_transparent(true)
-        .with_resizable(false)
+        .with_fullscreen(get_fullscreen(window_info, monitor))
+        .with_transparent(window_info.transparent)
+        .with_decorations(window_info.decorations)
+        .with_resizable(window_info.resizable)
+}
+
+fn get_fullscreen(window_info: &WindowInfo, monitor: Option<MonitorHandle>) -> Option<Fullscreen> {
+    match window_info.mode {
+        WindowMode::Windowed => None,
+        WindowMode::BorderlessFullscreen => Some(Fullscreen::Borderless(monitor)),
+        WindowMode::Fullscreen => {
+            let Some(monitor) = monitor else {
+                log::warn!("no monitor found for exclusive fullscreen, falling back to windowed");
+                return None;
+            };
+            // Prefer a video mode matching the window size, otherwise take the largest one
+            let size = PhysicalSize::new(window_info.width, window_info.height);
+            let video_mode = monitor.video_modes()
+                .filter(|mode| mode.size() == size)
+                .max_by_key(|mode| mode.refresh_rate_millihertz())
+                .or_else(|| monitor.video_modes().max_by_key(|mode| {
+                    (mode.size().width * mode.size().height, mode.refresh_rate_millihertz())
+                }))?;
+            Some(Fullscreen::Exclusive(video_mode))
+        },
+    }
 }
\ No newline at end of file